@@ -0,0 +1,293 @@
+use std::env;
+use std::fs::File;
+use std::fmt::Debug;
+use std::ops::Bound;
+use std::path::Path;
+use std::process::exit;
+
+use ddb::BTree;
+use ddb::Comparator;
+use ddb::Db;
+use ddb::Error;
+use ddb::ExportFormat;
+use ddb::Options;
+use ddb::ReadOnlyBTree;
+use ddb::SlotBytes;
+use ddb::SlotText;
+
+
+const USAGE: &str = "\
+usage: ddb <file> <command> [args] [--key TYPE] [--value TYPE] [--tree NAME] [--siblings] [--format FORMAT]
+
+commands:
+  get KEY
+  put KEY VALUE
+  delete KEY
+  scan [FROM] [TO]    entries with FROM <= key < TO
+  dump                every page of the tree
+  stats               entries, height, page counts, fill and free space
+  pages               one line per page of the tree
+  check               problems found walking the tree, leaked pages of a single tree file
+  dot                 Graphviz graph of the tree, --siblings links neighbouring leaves
+  export [PATH]       every entry in key order to PATH or stdout
+  import [PATH]       entries from PATH or stdin, existing keys are overwritten
+  backup PATH         copy of the tree as of one moment to a new single tree file
+
+types: u8, u16 (default for keys), u32, string (default for values), bytes (hex)
+formats: jsonl (default), csv
+--tree opens a named tree of a Db file instead of a single tree file";
+
+struct Args {
+    file: String,
+    command: String,
+    operands: Vec<String>,
+    key_type: String,
+    value_type: String,
+    tree: Option<String>,
+    siblings: bool,
+    format: ExportFormat,
+}
+
+impl Args {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut positional = vec![];
+        let mut key_type = "u16".to_string();
+        let mut value_type = "string".to_string();
+        let mut tree = None;
+        let mut siblings = false;
+        let mut format = ExportFormat::JsonLines;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut option_value = || args.next().ok_or(format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--key" => key_type = option_value()?,
+                "--value" => value_type = option_value()?,
+                "--tree" => tree = Some(option_value()?),
+                "--siblings" => siblings = true,
+                "--format" => format = match option_value()?.as_str() {
+                    "jsonl" => ExportFormat::JsonLines,
+                    "csv" => ExportFormat::Csv,
+                    other => return Err(format!("unknown format: {}", other)),
+                },
+                "-h" | "--help" => return Err(String::new()),
+                _ => positional.push(arg),
+            }
+        }
+        if positional.len() < 2 {
+            return Err("missing file or command".to_string());
+        }
+        let operands = positional.split_off(2);
+        Ok(Args { file: positional.remove(0), command: positional.remove(0), operands, key_type, value_type, tree, siblings, format })
+    }
+
+    // the commands that make the file or tree when it is missing
+    fn creates(&self) -> bool {
+        self.command == "put" || self.command == "import"
+    }
+
+    // the others open the file read-only, sharing it with other readers
+    fn writes(&self) -> bool {
+        self.creates() || self.command == "delete"
+    }
+
+    fn operand(&self, index: usize, name: &str) -> Result<&str, String> {
+        self.operands.get(index).map(|s| s.as_str()).ok_or(format!("{} needs {}", self.command, name))
+    }
+}
+
+fn main() {
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("ddb: {}", message);
+            }
+            eprintln!("{}", USAGE);
+            exit(2);
+        },
+    };
+    let result = match args.key_type.as_str() {
+        "u8" => with_value::<u8>(&args),
+        "u16" => with_value::<u16>(&args),
+        "u32" => with_value::<u32>(&args),
+        "string" => with_value::<String>(&args),
+        "bytes" => with_value::<Vec<u8>>(&args),
+        other => Err(format!("unknown key type: {}", other)),
+    };
+    if let Err(message) = result {
+        eprintln!("ddb: {}", message);
+        exit(1);
+    }
+}
+
+fn with_value<K>(args: &Args) -> Result<(), String>
+    where K: SlotText + Ord + SlotBytes + Clone + Debug,
+{
+    match args.value_type.as_str() {
+        "u8" => run::<K, u8>(args),
+        "u16" => run::<K, u16>(args),
+        "u32" => run::<K, u32>(args),
+        "string" => run::<K, String>(args),
+        "bytes" => run::<K, Vec<u8>>(args),
+        other => Err(format!("unknown value type: {}", other)),
+    }
+}
+
+fn run<K, V>(args: &Args) -> Result<(), String>
+    where K: SlotText + Ord + SlotBytes + Clone + Debug,
+          V: SlotText + SlotBytes + Clone + Debug,
+{
+    if !args.creates() && !Path::new(&args.file).exists() {
+        return Err(format!("no such file: {}", args.file));
+    }
+    if args.writes() {
+        write(&open::<K, V>(args)?, args)
+    } else {
+        read(&open_read_only::<K, V>(args)?, args)
+    }
+}
+
+fn write<K, V>(btree: &BTree<K, V>, args: &Args) -> Result<(), String>
+    where K: SlotText + Ord + SlotBytes + Clone + Debug,
+          V: SlotText + SlotBytes + Clone + Debug,
+{
+    match args.command.as_str() {
+        "put" => {
+            let key = K::from_text(args.operand(0, "KEY")?)?;
+            let value = V::from_text(args.operand(1, "VALUE")?)?;
+            if btree.search(&key).is_ok() {
+                btree.update(&key, value).map_err(show_error)?;
+            } else {
+                btree.insert(key, value).map_err(show_error)?;
+            }
+        },
+        "delete" => {
+            let key = K::from_text(args.operand(0, "KEY")?)?;
+            btree.search(&key).map_err(show_error)?;
+            btree.delete(&key);
+        },
+        "import" => {
+            let count = match args.operands.first() {
+                Some(path) => btree.import(File::open(path).map_err(|e| e.to_string())?, args.format),
+                None => btree.import(std::io::stdin().lock(), args.format),
+            };
+            eprintln!("imported {}", count.map_err(show_error)?);
+        },
+        other => return Err(format!("unknown command: {}", other)),
+    }
+    btree.flush().map_err(show_error)
+}
+
+fn read<K, V>(btree: &ReadOnlyBTree<K, V>, args: &Args) -> Result<(), String>
+    where K: SlotText + Ord + SlotBytes + Clone + Debug,
+          V: SlotText + SlotBytes + Clone + Debug,
+{
+    match args.command.as_str() {
+        "get" => {
+            let key = K::from_text(args.operand(0, "KEY")?)?;
+            let value = btree.search(&key).map_err(show_error)?;
+            println!("{}", value.to_text());
+        },
+        "scan" => {
+            let from = match args.operands.first() {
+                Some(from) => Bound::Included(K::from_text(from)?),
+                None => Bound::Unbounded,
+            };
+            let to = match args.operands.get(1) {
+                Some(to) => Bound::Excluded(K::from_text(to)?),
+                None => Bound::Unbounded,
+            };
+            for (key, value) in btree.range((from, to)) {
+                println!("{}\t{}", key.to_text(), value.to_text());
+            }
+        },
+        "dump" => print!("{:?}", btree),
+        "stats" => {
+            let stats = btree.stats();
+            println!("entries\t{}", stats.keys);
+            println!("height\t{}", stats.height);
+            println!("branch pages\t{}", stats.branch_pages);
+            println!("leaf pages\t{}", stats.leaf_pages);
+            println!("fill\tmin {:.0}% average {:.0}% max {:.0}%", 100.0 * stats.min_fill, 100.0 * stats.average_fill, 100.0 * stats.max_fill);
+            println!("free space\t{}", stats.free_space);
+            println!("fragmented bytes\t{}", stats.fragmented_bytes);
+            println!("free pages\t{}", stats.free_pages);
+        },
+        "pages" => {
+            for page in btree.pages() {
+                let kind = if page.branch { "branch" } else { "leaf" };
+                println!("{}\t{}\tdepth={}\tkeys={}\tfree={}\tfragmented={}",
+                    page.page_id, kind, page.depth, page.keys, page.free_space, page.fragmented_bytes);
+            }
+        },
+        "check" => {
+            let problems = btree.check();
+            for problem in &problems {
+                println!("{:?}", problem);
+            }
+            if !problems.is_empty() {
+                return Err(format!("problems found: {}", problems.len()));
+            }
+        },
+        "dot" => print!("{}", btree.to_dot_with(args.siblings)),
+        "export" => {
+            let count = match args.operands.first() {
+                Some(path) => btree.export(File::create(path).map_err(|e| e.to_string())?, args.format),
+                None => btree.export(std::io::stdout().lock(), args.format),
+            };
+            eprintln!("exported {}", count.map_err(show_error)?);
+        },
+        "backup" => {
+            let count = btree.backup_to(args.operand(0, "PATH")?).map_err(show_error)?;
+            eprintln!("backed up {} pages", count);
+        },
+        other => return Err(format!("unknown command: {}", other)),
+    }
+    Ok(())
+}
+
+fn open<K, V>(args: &Args) -> Result<BTree<K, V>, String>
+    where K: Ord + SlotBytes + Clone + Debug,
+          V: SlotBytes + Clone + Debug,
+{
+    match &args.tree {
+        Some(name) => {
+            let db = Db::open(&args.file).map_err(show_error)?;
+            match db.open_tree(name) {
+                Err(Error::NotFound) | Err(Error::NoPage) if args.creates() => db.create_tree(name).map_err(show_error),
+                Err(Error::NotFound) | Err(Error::NoPage) => Err(format!("no tree named {}", name)),
+                result => result.map_err(show_error),
+            }
+        },
+        None => BTree::create_with_comparator(&args.file, Options::default(), Comparator::natural()).map_err(show_error),
+    }
+}
+
+fn open_read_only<K, V>(args: &Args) -> Result<ReadOnlyBTree<K, V>, String>
+    where K: Ord + SlotBytes + Clone + Debug,
+          V: SlotBytes + Clone + Debug,
+{
+    match &args.tree {
+        Some(name) => {
+            let db = Db::open_read_only(&args.file).map_err(show_error)?;
+            match db.open_tree_read_only(name) {
+                Err(Error::NotFound) | Err(Error::NoPage) => Err(format!("no tree named {}", name)),
+                result => result.map_err(show_error),
+            }
+        },
+        None => BTree::open_read_only(&args.file).map_err(show_error),
+    }
+}
+
+fn show_error(error: Error) -> String {
+    match error {
+        Error::NoPage => "the tree is empty".to_string(),
+        Error::NotFound => "not found".to_string(),
+        Error::ComparatorMismatch(name) => format!("the tree was created with the {} comparator", name),
+        Error::TreeExists(name) => format!("a tree named {} already exists", name),
+        Error::Io(message) => message,
+        Error::Locked => "the file is in use by another process".to_string(),
+        Error::Parse(line, message) => format!("line {}: {}", line, message),
+        error => format!("{:?}", error),
+    }
+}
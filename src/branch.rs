@@ -1,3 +1,4 @@
+use std::cmp::Ordering;
 use std::fmt::Debug;
 use std::fmt::Error;
 use std::fmt::Formatter;
@@ -8,13 +9,13 @@ use crate::slot::SlotBytes;
 use crate::slotted::pointer::BranchPointer;
 
 
-pub struct Branch<K: Ord + SlotBytes + Debug> { pub slotted: Slotted<K, u16, BranchPointer> }
+pub struct Branch<K: SlotBytes + Debug> { pub slotted: Slotted<K, u16, BranchPointer> }
 
-impl<K: Ord + SlotBytes + Debug> Branch<K> {
+impl<K: SlotBytes + Debug> Branch<K> {
     pub fn new(mut slotted: Slotted<K, u16, BranchPointer>) -> Self {
         
         slotted.set_node_type(NodeType::Branch);
-        Branch { slotted: slotted }
+        Branch { slotted }
     }
 
     pub fn set_max_page_id(&mut self, number: u16) {
@@ -24,9 +25,17 @@ impl<K: Ord + SlotBytes + Debug> Branch<K> {
     pub fn max_page_id(&self) -> u16 {
         self.slotted.page.u16_bytes(4)
     }
+
+    pub fn child_page_id(&self, key: &K) -> u16 {
+        let compare = self.slotted.compare();
+        self.slotted.slots().into_iter()
+            .find(|(k, _)| compare(key, k) == Ordering::Less)
+            .map(|(_, page_id)| page_id)
+            .unwrap_or_else(|| self.max_page_id())
+    }
 }
 
-impl<K: Ord + SlotBytes + Debug> Debug for Branch<K> {
+impl<K: SlotBytes + Debug> Debug for Branch<K> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         let _  = write!(f, "({:?}): ", self.slotted.page.id);
         for (k, v) in self.slotted.slots() {
@@ -39,9 +48,9 @@ impl<K: Ord + SlotBytes + Debug> Debug for Branch<K> {
 // #[repr(C)]
 // #[derive(Debug, Clone, Copy)]
 // struct Header {
-//     number_of_pointer: u16,
+//     number_of_pointer: u16, // top bits: branch, prefix compressed, varint pointers
 //     end_of_free_space: u16,
 //     max_pointer: u16,
-//     _padding3: u16,
+//     fragmented_bytes: u16,
 // }
 
@@ -1,16 +1,37 @@
+mod backup;
+mod check;
+mod dot;
+mod expiry;
+mod export;
 mod fmt;
+mod read_only;
+mod snapshot;
+mod stats;
+mod vacuum;
 #[cfg(test)] mod test;
 
-use std::cell::RefCell;
 use std::fmt::Debug;
+use std::io;
+use std::marker::PhantomData;
+use std::ops::RangeBounds;
 use std::path::Path;
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::sync::atomic::AtomicU16;
+use std::sync::atomic::Ordering;
 
 use crate::error::Error;
+use crate::latch::Latch;
+use crate::latch::LatchMode;
 use crate::branch::Branch;
+use crate::comparator::Comparator;
+use crate::comparator::NATURAL;
 use crate::leaf::Leaf;
 use crate::meta::Meta;
 use crate::node::Node;
 use crate::node::NodeType;
+use crate::options::Options;
+use crate::page::PAGE_SIZE;
 use crate::page::Page;
 use crate::slot::Slot;
 use crate::slot::SlotBytes;
@@ -19,330 +40,561 @@ use crate::slotted::pointer::BranchPointer;
 use crate::slotted::pointer::LeafPointer;
 use crate::slotted::pointer::Pointer;
 use crate::storage::Storage;
+use crate::store::FileStore;
+use crate::store::PageStore;
+use crate::store::io_error;
+
+pub use check::Problem;
+pub use expiry::Expiring;
+pub use read_only::ReadOnlyBTree;
+pub use snapshot::PageInfo;
+pub use snapshot::Snapshot;
+pub use stats::Stats;
 
 
 pub struct BTree<K, V> {
-    root_page_id: Option<u16>,
-    storage: RefCell<Storage<K, V>>,
+    meta_page_id: u16,
+    // 0 while the tree is empty, page 0 is always a meta page
+    root_page_id: AtomicU16,
+    options: Options,
+    comparator: Comparator<K>,
+    max_key_size: AtomicU16,
+    // held exclusively to create the root, for updates and to take snapshots, everything else latches pages
+    latch: RwLock<()>,
+    storage: Arc<Storage>,
+    _phantom_value: PhantomData<fn() -> V>,
+}
+
+impl<K, V> BTree<K, V> {
+    pub fn meta_page_id(&self) -> u16 {
+        self.meta_page_id
+    }
+
+    pub fn root_page_id(&self) -> Option<u16> {
+        match self.root_page_id.load(Ordering::SeqCst) {
+            0 => None,
+            page_id => Some(page_id),
+        }
+    }
+}
+
+impl<K, V> Drop for BTree<K, V> {
+    fn drop(&mut self) {
+        self.storage.close_handle(self.meta_page_id);
+    }
 }
 
 impl<K, V> BTree<K, V>
-    where K: Ord + SlotBytes + Debug,
+    where K: Ord + SlotBytes + Clone + Debug,
           V: SlotBytes + Clone + Debug,
 {
     pub fn create(file_path: impl AsRef<Path>) -> Self {
-        let mut storage = Storage::from_path(file_path);
+        match Self::create_with_options(file_path, Options::default()) {
+            Ok(btree) => btree,
+            Err(error) => panic!("{:?}: the tree could not be opened", error),
+        }
+    }
+
+    pub fn create_with_options(file_path: impl AsRef<Path>, options: Options) -> Result<Self, Error> {
+        Self::create_with_comparator(file_path, options, Comparator::natural())
+    }
+}
 
-        let root_page_id = if storage.next_page_id > 0 {
-            let mut meta_page = Page::new(0);
-            storage.read_page(&mut meta_page);
-            let meta = Meta::new(meta_page);    
-            Some(meta.root_page_id())
+impl<K, V> BTree<K, V>
+    where K: SlotBytes + Debug,
+          V: SlotBytes + Clone + Debug,
+{
+    pub fn create_with_comparator(file_path: impl AsRef<Path>, options: Options, comparator: Comparator<K>) -> Result<Self, Error> {
+        Self::create_with_store(FileStore::open(file_path)?, options, comparator)
+    }
+
+    // opens the tree when the store already has pages
+    pub fn create_with_store(store: impl PageStore + 'static, options: Options, comparator: Comparator<K>) -> Result<Self, Error> {
+        let storage = Arc::new(Storage::new(Box::new(store), options.durability).map_err(io_error)?);
+        if storage.next_page_id() > 0 {
+            Self::open_in(storage, 0, options, comparator)
         } else {
-            Default::default()
-        };
+            Self::create_in(storage, options, comparator)
+        }
+    }
+
+    pub(crate) fn create_in(storage: Arc<Storage>, options: Options, comparator: Comparator<K>) -> Result<Self, Error> {
+        let mut meta = Meta::new(storage.allocate_page().map_err(io_error)?);
+        meta.set_prefix_compression(options.prefix_compression);
+        meta.set_pointer_format(options.pointer_format);
+        meta.set_comparator_name(comparator.name);
+        storage.write_page(&mut meta.page).map_err(io_error)?;
+        Ok(Self::new(storage, meta.page.id, options, comparator, 0, 0))
+    }
+
+    pub(crate) fn open_in(storage: Arc<Storage>, meta_page_id: u16, mut options: Options, comparator: Comparator<K>) -> Result<Self, Error> {
+        let mut meta_page = Page::new(meta_page_id);
+        storage.read_page(&mut meta_page).map_err(io_error)?;
+        let meta = Meta::new(meta_page);
+        let comparator_name = meta.comparator_name();
+        let comparator_name = if comparator_name.is_empty() { NATURAL } else { &comparator_name };
+        if comparator_name != comparator.name {
+            return Err(Error::ComparatorMismatch(comparator_name.to_string()));
+        }
+        options.prefix_compression = meta.prefix_compression();
+        options.pointer_format = meta.pointer_format();
+        Ok(Self::new(storage, meta_page_id, options, comparator, meta.root_page_id(), meta.max_key_size()))
+    }
 
+    fn new(storage: Arc<Storage>, meta_page_id: u16, options: Options, comparator: Comparator<K>, root_page_id: u16, max_key_size: u16) -> Self {
+        storage.open_handle(meta_page_id);
         BTree {
-            root_page_id: root_page_id,
-            storage: RefCell::new(storage),
+            meta_page_id,
+            root_page_id: AtomicU16::new(root_page_id),
+            options,
+            comparator,
+            max_key_size: AtomicU16::new(max_key_size),
+            latch: RwLock::new(()),
+            storage,
+            _phantom_value: PhantomData,
         }
     }
 
     pub fn search(&self, key: &K) -> Result<V, Error> where 
         V: SlotBytes
     {
-        if let Some(root_page_id) = self.root_page_id {
+        let _latch = self.latch.read().unwrap();
+        if let Some(root_latch) = self.latch_root(LatchMode::Shared) {
             let mut breadcrumb = vec![];
-            self.search_internal(root_page_id, key, &mut breadcrumb)
+            self.search_internal(root_latch, key, &mut breadcrumb)
         } else {
             Err(Error::NoPage)
         }
     }
 
-    pub fn insert(&mut self, key: K, value: V)
+    // a read or write that fails stops the insert, flush returns a failed write as well
+    pub fn insert(&self, key: K, value: V) -> Result<(), Error>
         where
             K: SlotBytes + Clone,
             V: SlotBytes + Clone,
     {
-        if let Some(root_page_id) = self.root_page_id {
-            let mut breadcrumb = vec![];
-            self.insert_internal(root_page_id, key, value, &mut breadcrumb);
+        self.check_fits(&key, &value)?;
+        if self.root_page_id().is_none() {
+            let _latch = self.latch.write().unwrap();
+            if self.root_page_id().is_none() {
+                let _writing = self.storage.begin_write();
+                return self.create_root(key, value).map_err(io_error);
+            }
+        }
+        let _latch = self.latch.read().unwrap();
+        let _writing = self.storage.begin_write();
+        self.insert_from_root(key, value).map_err(io_error)
+    }
+
+    pub fn update(&self, key: &K, value: V) -> Result<(), Error>
+        where
+            K: SlotBytes + Clone,
+            V: SlotBytes + Clone,
+    {
+        self.check_fits(key, &value)?;
+        let _latch = self.latch.write().unwrap();
+        let _writing = self.storage.begin_write();
+        if let Some(root_page_id) = self.root_page_id() {
+            self.update_internal(root_page_id, key, value, false)
         } else {
-            let root_page_id = 1;
-            self.root_page_id = Some(root_page_id);
-
-            let meta_page = self.storage.borrow_mut().allocate_page();
-            let mut meta = Meta::new(meta_page);
-            meta.set_root_page_id(root_page_id);
-            self.storage.borrow_mut().write_page(&mut meta.page);
-
-            let mut leaf = self.create_leaf();
-            let slot = Slot::new(key, value);
-            let _ = leaf.slotted.insert(&slot);
-            self.write_leaf(&mut leaf);
+            Err(Error::NoPage)
         }
     }
 
-    pub fn delete(&mut self, key: &K) where K: SlotBytes {
-        if let Some(root_page_id) = self.root_page_id {
-            self.delete_internal(root_page_id, key);
+    // a read or write that fails is returned by flush
+    pub fn delete(&self, key: &K) where K: SlotBytes {
+        let _latch = self.latch.read().unwrap();
+        let _writing = self.storage.begin_write();
+        // deletes never merge pages, so no ancestor has to stay latched
+        if let Some(root_latch) = self.latch_root(LatchMode::Exclusive) {
+            let _ = self.storage.keep_error(self.delete_internal(root_latch, key));
         }
     }
 
-    fn delete_internal(&mut self, page_id: u16, key: &K) {
-        println!("delete_internal: page_id: {:?} key: {:?}", page_id, key);
-        let node = self.read_node(page_id);
-        match node {
+    // waits for writers in flight, later writes keep the pages it reads
+    pub fn snapshot(&self) -> Snapshot<'_, K, V> {
+        let _latch = self.latch.write().unwrap();
+        Snapshot::new(self, self.storage.bump_version(), self.root_page_id())
+    }
+
+    pub fn range(&self, range: impl RangeBounds<K>) -> Vec<(K, V)> {
+        self.snapshot().range(range)
+    }
+
+    pub fn pages(&self) -> Vec<PageInfo> {
+        self.snapshot().pages()
+    }
+
+    // flushes the whole file, other trees of a Db included
+    pub fn flush(&self) -> Result<(), Error> {
+        self.storage.flush().map_err(io_error)
+    }
+
+    // checked before anything is allocated, so a split always leaves both halves within a page
+    fn check_fits(&self, key: &K, value: &V) -> Result<(), Error>
+        where
+            K: SlotBytes + Clone,
+            V: SlotBytes + Clone,
+    {
+        let leaf = self.create_leaf(Page::new(0));
+        let branch = self.create_branch(Page::new(0));
+        if leaf.slotted.built_len(&[(key.clone(), value.clone())]) > PAGE_SIZE
+            || branch.slotted.built_len(&vec![(key.clone(), 0); 3]) > PAGE_SIZE {
+            return Err(Error::TooLarge);
+        }
+        Ok(())
+    }
+
+    fn create_root(&self, key: K, value: V) -> io::Result<()>
+        where
+            K: SlotBytes + Clone,
+            V: SlotBytes + Clone,
+    {
+        let key_size = key.into_bytes().len() as u16;
+        self.max_key_size.store(key_size, Ordering::SeqCst);
+
+        let mut leaf = self.create_leaf(self.storage.allocate_page()?);
+        let slot = Slot::new(key, value);
+        let _ = leaf.slotted.insert(&slot);
+        self.write_leaf(&mut leaf)?;
+
+        let _meta_latch = self.storage.latches.lock(self.meta_page_id, LatchMode::Exclusive);
+        let mut meta = self.read_meta()?;
+        meta.set_root_page_id(leaf.slotted.page.id);
+        meta.set_max_key_size(key_size);
+        self.storage.write_page(&mut meta.page)?;
+        self.root_page_id.store(leaf.slotted.page.id, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn insert_from_root(&self, key: K, value: V) -> io::Result<()>
+        where
+            K: SlotBytes + Clone,
+            V: SlotBytes + Clone,
+    {
+        self.grow_max_key_size(&key)?;
+        let root_latch = self.latch_root(LatchMode::Exclusive).unwrap();
+        let mut breadcrumb = vec![];
+        let mut latches = vec![root_latch];
+        self.insert_internal(key, value, &mut breadcrumb, &mut latches)
+    }
+
+    // the root may have been split while we waited for its latch
+    fn latch_root(&self, mode: LatchMode) -> Option<Latch<'_>> {
+        loop {
+            let root_page_id = self.root_page_id()?;
+            let latch = self.storage.latches.lock(root_page_id, mode);
+            if self.root_page_id() == Some(root_page_id) {
+                return Some(latch);
+            }
+        }
+    }
+
+    fn grow_max_key_size(&self, key: &K) -> io::Result<()> {
+        let key_size = key.into_bytes().len() as u16;
+        if self.max_key_size.fetch_max(key_size, Ordering::SeqCst) < key_size {
+            let _meta_latch = self.storage.latches.lock(self.meta_page_id, LatchMode::Exclusive);
+            let mut meta = self.read_meta()?;
+            meta.set_max_key_size(self.max_key_size.load(Ordering::SeqCst));
+            self.storage.write_page(&mut meta.page)?;
+        }
+        Ok(())
+    }
+
+    // an expired entry is not found unless it is to be replaced anyway
+    fn update_internal(&self, page_id: u16, key: &K, value: V, replace_expired: bool) -> Result<(), Error>
+        where
+            K: SlotBytes + Clone,
+            V: SlotBytes + Clone,
+    {
+        match self.read_node(page_id).map_err(io_error)? {
             Node::Leaf(mut leaf) => {
-                let _ = leaf.slotted.delete(key);
+                if !replace_expired && leaf.slotted.search(key).is_some_and(|old| expiry::expired_now(&old)) {
+                    return Err(Error::NotFound);
+                }
+                match leaf.slotted.update(key, &value) {
+                    Err(Error::FullLeaf) if leaf.slotted.fragmented_bytes() > 0 => {
+                        leaf.slotted.compact();
+                        if leaf.slotted.update(key, &value).is_err() {
+                            return self.reinsert(leaf, key, value);
+                        }
+                    },
+                    Err(Error::FullLeaf) => return self.reinsert(leaf, key, value),
+                    result => result?,
+                }
+                self.write_leaf(&mut leaf).map_err(io_error)
             },
             Node::Branch(branch) => {
-                for k in branch.slotted.keys() {
-                    if key < &k {
-                        let child_page_id = branch.slotted.search(&k).unwrap();
-                        return self.delete_internal(child_page_id, key);
-                    }
-                }
-                self.delete_internal(branch.max_page_id(), key);
+                let child_page_id = branch.child_page_id(key);
+                self.update_internal(child_page_id, key, value, replace_expired)
             },
         }
     }
 
-    fn search_internal<Val: Debug>(&self, page_id: u16, key: &K, breadcrumb: &mut Vec<u16>) -> Result<Val, Error>
-        where Val: SlotBytes
+    fn reinsert(&self, mut leaf: Leaf<K, V>, key: &K, value: V) -> Result<(), Error>
+        where
+            K: SlotBytes + Clone,
+            V: SlotBytes + Clone,
     {
-        let mut page = Page::new(page_id);
-        self.storage.borrow_mut().read_page(&mut page);
+        leaf.slotted.delete(key)?;
+        self.write_leaf(&mut leaf).map_err(io_error)?;
+        self.insert_from_root(key.clone(), value).map_err(io_error)
+    }
 
-        match Node::new(page) {
+    fn delete_internal(&self, latch: Latch, key: &K) -> io::Result<()> {
+        let node = self.read_node(latch.page_id)?;
+        match node {
+            Node::Leaf(mut leaf) => {
+                if leaf.slotted.delete(key).is_ok() {
+                    self.write_leaf(&mut leaf)?;
+                }
+                Ok(())
+            },
+            Node::Branch(branch) => {
+                let child_page_id = branch.child_page_id(key);
+                let child_latch = self.storage.latches.lock(child_page_id, LatchMode::Exclusive);
+                drop(latch);
+                self.delete_internal(child_latch, key)
+            },
+        }
+    }
+
+    fn search_internal(&self, latch: Latch, key: &K, breadcrumb: &mut Vec<u16>) -> Result<V, Error> {
+        match self.read_node(latch.page_id).map_err(io_error)? {
             Node::Leaf(leaf) => {
-                leaf.slotted.search(key).ok_or(Error::NotFound)
+                leaf.slotted.search(key).filter(|value| !expiry::expired_now(value)).ok_or(Error::NotFound)
             },
             Node::Branch(branch) => {
                 breadcrumb.push(branch.slotted.page.id);
-                for k in branch.slotted.keys() {
-                    if key < &k {
-                        let child_page_id = branch.slotted.search(&k).unwrap();
-                        return self.search_internal(child_page_id, key, breadcrumb);
-                    }
-                }
-                self.search_internal(branch.max_page_id(), key, breadcrumb)
+                let child_page_id = branch.child_page_id(key);
+                let child_latch = self.storage.latches.lock(child_page_id, LatchMode::Shared);
+                drop(latch);
+                self.search_internal(child_latch, key, breadcrumb)
             },
         }
     }
 
-    fn insert_internal<Val: Debug>(&mut self, page_id: u16, key: K, value: Val, breadcrumb: &mut Vec<u16>) 
-        where
-            K: SlotBytes + Clone,
-            Val: SlotBytes + Clone,
+    // latches holds the current page and every ancestor a split could still reach
+    fn insert_internal<'a>(&'a self, key: K, value: V, breadcrumb: &mut Vec<u16>, latches: &mut Vec<Latch<'a>>) -> io::Result<()>
+        where K: SlotBytes + Clone,
     {
+        let page_id = latches.last().unwrap().page_id;
         // println!("insert_internal: page_id: {:?} key: {:?} value: {:?} breadcrumb: {:?}", &page_id, &key, &value, &breadcrumb);
-        let mut page = Page::new(page_id);
-        self.storage.borrow_mut().read_page(&mut page);
-
-        match Node::new(page) {
+        match self.read_node(page_id)? {
             Node::Leaf(mut leaf) => {
                 let slot = Slot::new(key, value);
+                if leaf.slotted.has_room_for(slot.key_size() as usize, slot.value_size() as usize) {
+                    latches.drain(..latches.len() - 1);
+                }
+                if leaf.slotted.is_full(&slot) && (leaf.slotted.fragmented_bytes() > 0 || leaf.slotted.is_prefix_compressed()) {
+                    leaf.slotted.compact();
+                }
                 match leaf.slotted.insert(&slot) {
-                    Ok(_) => {
-                        self.write_leaf(&mut leaf);
-                    },
-                    Err(_) => {
-                        self.split(&mut leaf.slotted, slot, breadcrumb);
-                    },
+                    Ok(_) => self.write_leaf(&mut leaf),
+                    Err(_) => self.split(&mut leaf.slotted, slot, breadcrumb),
                 }
             },
             Node::Branch(branch) => {
-                breadcrumb.push(branch.slotted.page.id);
-                for k in branch.slotted.keys() {
-                    if key < k {
-                        let child_page_id = branch.slotted.search(&k).unwrap();
-                        return self.insert_internal(child_page_id, key, value, breadcrumb);
-                    }
+                let max_key_size = self.max_key_size.load(Ordering::SeqCst) as usize;
+                if branch.slotted.has_room_for(max_key_size, 2) {
+                    latches.drain(..latches.len() - 1);
                 }
-                self.insert_internal(branch.max_page_id(), key, value, breadcrumb)
+                breadcrumb.push(branch.slotted.page.id);
+                let child_page_id = branch.child_page_id(&key);
+                latches.push(self.storage.latches.lock(child_page_id, LatchMode::Exclusive));
+                self.insert_internal(key, value, breadcrumb, latches)
             },
         }
     }
 
-    fn split<Val, Ptr>(&mut self, slotted: &mut Slotted<K, Val, Ptr>, slot: Slot<K, Val>, breadcrumb: &mut Vec<u16>)
+    fn split<Val, Ptr>(&self, slotted: &mut Slotted<K, Val, Ptr>, slot: Slot<K, Val>, breadcrumb: &mut Vec<u16>) -> io::Result<()>
         where K: SlotBytes + Clone,
               Val: SlotBytes + Clone + Debug,
               Ptr: Pointer + Debug,
     {
         // println!("split: slotted: {:?} slot: {:?} breadcrumb: {:?}", &slotted.slots(), &slot, &breadcrumb);
 
-        let new_page = self.storage.borrow_mut().allocate_page();
-        let mut new_slotted = Slotted::<K, Val, Ptr>::create(new_page);
-        new_slotted.set_node_type(NodeType::new(&slotted.page));
-
-        let mut keys = slotted.keys();
+        // nothing is allocated for a split that cannot be made
+        let is_branch = NodeType::new(&slotted.page) == NodeType::Branch;
+        let mut slots = slotted.slots();
+        let position = slots.iter().position(|(k, _)| self.comparator.less(&slot.key, k)).unwrap_or(slots.len());
+        slots.insert(position, (slot.key, slot.value));
+        let split_point = Self::split_point(slotted, &slots, is_branch)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no split leaves both halves within a page"))?;
 
-        self.transfer_slots(&mut keys, slotted, &mut new_slotted, &slot);
+        let new_page = self.storage.allocate_page()?;
+        let mut new_slotted = Slotted::<K, Val, Ptr>::create(new_page, slotted.compare());
+        new_slotted.set_node_type(NodeType::new(&slotted.page));
+        new_slotted.set_prefix_compression(slotted.is_prefix_compressed());
+        new_slotted.set_pointer_format(slotted.pointer_format());
 
-        let mut parent_branch = self.parent_branch(&mut new_slotted, breadcrumb);
+        let split_key = self.transfer_slots(slotted, &mut new_slotted, slots, split_point);
 
-        self.update_parent_branch(&keys, slotted, &mut new_slotted, breadcrumb, &mut parent_branch);
+        self.write_splitted_pages(slotted, &mut new_slotted)?;
 
-        self.write_splitted_pages(slotted, &mut new_slotted, &mut parent_branch);
+        self.update_parent_branch(split_key, slotted.page.id, new_slotted.page.id, breadcrumb)
     }
 
-    fn transfer_slots<Val, Ptr>(&mut self,
-        keys: &mut Vec<K>,
+    fn transfer_slots<Val, Ptr>(&self,
         old_slotted: &mut Slotted<K, Val, Ptr>, 
         new_slotted: &mut Slotted<K, Val, Ptr>, 
-        slot: &Slot<K, Val>
-    )
+        mut lower: Vec<(K, Val)>,
+        split_point: usize,
+    ) -> K
         where K: SlotBytes + Clone,
               Val: SlotBytes + Clone + Debug,
               Ptr: Pointer + Debug,
     {
-        keys.push(slot.key.clone());
-        keys.sort();
-        let mut new_slot_inserted = false;
-        for key in keys.split_at(keys.len() / 2).1.iter().rev() {
-            match old_slotted.search(key) {
-                Some(value) => {
-                    let _ = new_slotted.insert(&Slot::new(key.clone(), value));
-                    let _ = old_slotted.delete(key);
-                },
-                None => {
-                    let _ = new_slotted.insert(&slot);
-                    new_slot_inserted = true
-                }
-            }
-        }
+        let is_branch = NodeType::new(&old_slotted.page) == NodeType::Branch;
+        let mut upper = lower.split_off(split_point);
 
-        // transfer max_page_id
-        if NodeType::new(&old_slotted.page) == NodeType::Branch {
+        let split_key = if is_branch {
+            // the middle entry moves up; its child becomes max_page_id of the lower half
+            let (split_key, page_id) = upper.remove(0);
             let max_page_id = old_slotted.page.u16_bytes(4);
-            // 0 is treated as invalid page_id
-            old_slotted.page.set_u16_bytes(4, 0);
+            old_slotted.page.set_bytes(4, page_id.into_bytes());
             new_slotted.page.set_u16_bytes(4, max_page_id);
-        }
+            split_key
+        } else {
+            (self.comparator.separator)(&lower[lower.len() - 1].0, &upper[0].0)
+        };
 
-        if !new_slot_inserted {
-            let _ = old_slotted.insert(&slot);
-        }
+        // both halves may share a longer prefix now
+        old_slotted.set_slots(lower);
+        new_slotted.set_slots(upper);
 
         // println!("splitted! old: {:?} new: {:?}", &old_slotted, &new_slotted);
+        split_key
     }
 
-    fn parent_branch<Val, Ptr>(&mut self,
-        new_slotted: &mut Slotted<K, Val, Ptr>, 
-        breadcrumb: &mut Vec<u16>
-    ) -> Branch<K>
-        where K: SlotBytes + Clone,
-            Val: SlotBytes + Clone + Debug,
-            Ptr: Pointer + Debug,
+    // balanced by bytes, moved to the nearest point where both halves fit in a page
+    fn split_point<Val, Ptr>(slotted: &Slotted<K, Val, Ptr>, slots: &[(K, Val)], is_branch: bool) -> Option<usize>
+        where Val: SlotBytes + Debug,
+              Ptr: Pointer + Debug,
     {
-        let parent_branch = if breadcrumb.is_empty() {
-            // add new branch
-            let page = self.storage.borrow_mut().allocate_page();
-            let parent_slotted = Slotted::<K, u16, BranchPointer>::create(page);
-            let mut branch = Branch::new(parent_slotted);
-            branch.set_max_page_id(new_slotted.page.id);
-            branch
-        } else {
-            let page_id = breadcrumb[breadcrumb.len() - 1];
-            let mut page = Page::new(page_id);
-            self.storage.borrow_mut().read_page(&mut page);
-            let parent_slotted = Slotted::<K, u16, BranchPointer>::new(page);
-            Branch::new(parent_slotted)
-        };
-        // println!("parent_branch: {:?}", &parent_branch);
-        parent_branch
+        let pointer_size = slotted.pointer_size();
+        let sizes = slots.iter()
+            .map(|(k, v)| k.into_bytes().len() + v.into_bytes().len() + pointer_size)
+            .collect::<Vec<_>>();
+        let half = sizes.iter().sum::<usize>() / 2;
+        let mut filled = 0;
+        let mut balanced = 0;
+        for size in sizes {
+            if filled + size > half {
+                break;
+            }
+            filled += size;
+            balanced += 1;
+        }
+        // one slot cannot be split, the clamp below needs two
+        if slots.len() < 2 {
+            return None;
+        }
+        let balanced = balanced.clamp(1, slots.len() - 1);
+
+        // a branch moves the first entry of its upper half up to the parent
+        let skipped = if is_branch { 1 } else { 0 };
+        let fits = |point: usize| slotted.built_len(&slots[..point]) <= PAGE_SIZE
+            && slotted.built_len(&slots[point + skipped..]) <= PAGE_SIZE;
+        (1..slots.len())
+            .filter(|&point| fits(point))
+            .min_by_key(|&point| (point as isize - balanced as isize).abs())
     }
 
-    fn update_parent_branch<Val, Ptr>(&mut self,
-        keys: &Vec<K>,
-        old_slotted: &mut Slotted<K, Val, Ptr>, 
-        new_slotted: &mut Slotted<K, Val, Ptr>, 
-        breadcrumb: &mut Vec<u16>, 
-        parent_branch: &mut Branch<K>
-    )
+    fn update_parent_branch(&self, split_key: K, old_page_id: u16, new_page_id: u16, breadcrumb: &mut Vec<u16>) -> io::Result<()>
         where K: SlotBytes + Clone,
-            Val: SlotBytes + Clone + Debug,
-            Ptr: Pointer + Debug,
     {
-        let mut keys_iter = keys.split_at(keys.len() / 2).1.iter();
-        let split_key = keys_iter.next().unwrap();
-
-        if breadcrumb.is_empty() {
-            let _ = parent_branch.slotted.insert(&Slot::new(split_key.clone(), old_slotted.page.id));
-
-            // set root page id
-            self.set_root_page_id(parent_branch.slotted.page.id);
-        } else {
-            breadcrumb.pop();
-            let _ = self.insert_page_id_into_branch(parent_branch, split_key.clone(), old_slotted.page.id, breadcrumb);
-            // println!("slotted.page.id: {:?} parent_branch.max_page_id: {:?}", old_slotted.page.id, parent_branch.max_page_id());
-            if old_slotted.page.id == parent_branch.max_page_id() {
-                parent_branch.set_max_page_id(new_slotted.page.id);
-            } else {
-                let slots = parent_branch.slotted.slots();
-                let rewriting_key = slots.iter().rfind(|(_k, v)| v == &old_slotted.page.id).unwrap();
-                // println!("rewriting_key: {:?}", rewriting_key);
-
-                let _ = parent_branch.slotted.delete(&rewriting_key.0);
-                let _ = parent_branch.slotted.insert(&Slot::new(rewriting_key.0.clone(), new_slotted.page.id));
-            }
+        match breadcrumb.pop() {
+            None => {
+                // add new branch
+                let mut parent_branch = self.create_branch(self.storage.allocate_page()?);
+                parent_branch.set_max_page_id(new_page_id);
+                let _ = parent_branch.slotted.insert(&Slot::new(split_key, old_page_id));
+                self.storage.write_page(&mut parent_branch.slotted.page)?;
+
+                // set root page id
+                self.set_root_page_id(parent_branch.slotted.page.id)
+            },
+            Some(parent_page_id) => {
+                let mut page = Page::new(parent_page_id);
+                self.storage.read_page(&mut page)?;
+                let mut parent_branch = Branch::new(Slotted::<K, u16, BranchPointer>::new(page, self.comparator.compare));
+
+                // the entry that pointed to the old page now covers the upper half
+                if old_page_id == parent_branch.max_page_id() {
+                    parent_branch.set_max_page_id(new_page_id);
+                } else {
+                    let slots = parent_branch.slotted.slots();
+                    let rewriting_key = slots.iter().find(|(_k, v)| v == &old_page_id).unwrap();
+                    let _ = parent_branch.slotted.update(&rewriting_key.0, &new_page_id);
+                }
+                self.insert_page_id_into_branch(&mut parent_branch, split_key, old_page_id, breadcrumb)
+            },
         }
     }
 
-    fn write_splitted_pages<Val, Ptr>(&mut self, 
+    fn write_splitted_pages<Val, Ptr>(&self, 
         old_slotted: &mut Slotted<K, Val, Ptr>, 
         new_slotted: &mut Slotted<K, Val, Ptr>, 
-        parent_branch: &mut Branch<K>
-    )
+    ) -> io::Result<()>
         where K: SlotBytes + Clone,
             Val: SlotBytes + Clone + Debug,
             Ptr: Pointer + Debug,
     {
-        self.storage.borrow_mut().write_page(&mut old_slotted.page);
-        self.storage.borrow_mut().write_page(&mut new_slotted.page);
-        self.storage.borrow_mut().write_page(&mut parent_branch.slotted.page);
+        // the new page is complete before the old one gives up its upper half
+        self.storage.write_page(&mut new_slotted.page)?;
+        self.storage.write_page(&mut old_slotted.page)
     }
 
-    fn insert_page_id_into_branch(&mut self, branch: &mut Branch<K>, key: K, value: u16, breadcrumb: &mut Vec<u16>) 
+    fn insert_page_id_into_branch(&self, branch: &mut Branch<K>, key: K, value: u16, breadcrumb: &mut Vec<u16>) -> io::Result<()>
         where K: SlotBytes + Clone,
     {
         // println!("insert_page_id_into_branch: branch: {:?} key: {:?} value: {:?}", branch, key, value);
         let slot = Slot::new(key, value);
+        if branch.slotted.is_full(&slot) && branch.slotted.fragmented_bytes() > 0 {
+            branch.slotted.compact();
+        }
         match branch.slotted.insert(&slot) {
-            Ok(_) => {
-                self.storage.borrow_mut().write_page(&mut branch.slotted.page);
-            },
-            Err(_) => {
-                self.split(&mut branch.slotted, slot, breadcrumb);
-            },
+            Ok(_) => self.storage.write_page(&mut branch.slotted.page),
+            Err(_) => self.split(&mut branch.slotted, slot, breadcrumb),
         }
     }
 
-    fn create_leaf(&self) -> Leaf<K, V> {
-        let page = self.storage.borrow_mut().allocate_page();
-        let mut slotted = Slotted::<K, V, LeafPointer>::create(page);
+    fn create_branch(&self, page: Page) -> Branch<K> {
+        let mut slotted = Slotted::<K, u16, BranchPointer>::create(page, self.comparator.compare);
+        slotted.set_pointer_format(self.options.pointer_format);
+        Branch::new(slotted)
+    }
+
+    fn create_leaf(&self, page: Page) -> Leaf<K, V> {
+        let mut slotted = Slotted::<K, V, LeafPointer>::create(page, self.comparator.compare);
         slotted.set_node_type(NodeType::Leaf);
-        Leaf { slotted: slotted }
+        slotted.set_prefix_compression(self.options.prefix_compression);
+        slotted.set_pointer_format(self.options.pointer_format);
+        Leaf { slotted }
     }
 
-    fn write_leaf<Val: SlotBytes + Debug>(&self, leaf: &mut Leaf<K, Val>) {
-        self.storage.borrow_mut().write_page(&mut leaf.slotted.page);
+    fn write_leaf<Val: SlotBytes + Debug>(&self, leaf: &mut Leaf<K, Val>) -> io::Result<()> {
+        self.storage.write_page(&mut leaf.slotted.page)
     }
 
-    fn read_node(&self, page_id: u16) -> Node<K, V> {
+    fn read_node(&self, page_id: u16) -> io::Result<Node<K, V>> {
         let mut page = Page::new(page_id);
-        self.storage.borrow_mut().read_page(&mut page);
-        Node::new(page)
+        self.storage.read_page(&mut page)?;
+        Ok(Node::new(page, self.comparator.compare))
     }
 
-    fn set_root_page_id(&mut self, page_id: u16) {
-        self.root_page_id = Some(page_id);
-        let mut page = Page::new(0);
-        self.storage.borrow_mut().read_page(&mut page);
-        let mut meta = Meta::new(page);
-        meta.set_root_page_id(self.root_page_id.unwrap());
-        self.storage.borrow_mut().write_page(&mut meta.page);
+    fn read_meta(&self) -> io::Result<Meta> {
+        let mut page = Page::new(self.meta_page_id);
+        self.storage.read_page(&mut page)?;
+        Ok(Meta::new(page))
     }
-}
 
+    fn set_root_page_id(&self, page_id: u16) -> io::Result<()> {
+        self.root_page_id.store(page_id, Ordering::SeqCst);
+        let _meta_latch = self.storage.latches.lock(self.meta_page_id, LatchMode::Exclusive);
+        let mut meta = self.read_meta()?;
+        meta.set_root_page_id(self.root_page_id.load(Ordering::SeqCst));
+        self.storage.write_page(&mut meta.page)
+    }
+}
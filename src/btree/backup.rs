@@ -0,0 +1,61 @@
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+use crate::btree::BTree;
+use crate::error::Error;
+use crate::meta::Meta;
+use crate::page::Page;
+use crate::slot::SlotBytes;
+use crate::store::FileStore;
+use crate::store::PageStore;
+use crate::store::io_error;
+
+
+impl<K, V> BTree<K, V>
+    where K: SlotBytes + Debug,
+          V: SlotBytes + Clone + Debug,
+{
+    // a single tree file as of one snapshot, returns how many pages of the tree were copied
+    pub fn backup_to(&self, file_path: impl AsRef<Path>) -> Result<usize, Error> {
+        self.backup_to_store(&FileStore::open(file_path)?)
+    }
+
+    // pages keep their ids and are copied one at a time, writers carry on meanwhile;
+    // the meta page goes last to page 0 and the ids the tree does not use become the free list
+    pub fn backup_to_store(&self, store: &impl PageStore) -> Result<usize, Error> {
+        if !store.is_empty() {
+            return Err(Error::Io("a backup needs an empty file".to_string()));
+        }
+        let snapshot = self.snapshot();
+        let page_ids = snapshot.try_pages().map_err(io_error)?.into_iter().map(|page| page.page_id).collect::<Vec<_>>();
+        let reachable = page_ids.iter().cloned().collect::<HashSet<_>>();
+        let last_page_id = page_ids.iter().cloned().max().unwrap_or(0);
+        (0..=last_page_id).for_each(|_| { store.allocate(); });
+        let mut free_list_head = 0;
+        for page_id in 1..=last_page_id {
+            let page = if reachable.contains(&page_id) {
+                snapshot.read_page(page_id).map_err(io_error)?
+            } else {
+                let mut page = Page::new(page_id);
+                page.set_u16_bytes(0, free_list_head);
+                free_list_head = page_id;
+                page
+            };
+            store.write_page(page_id, &page.bytes).map_err(io_error)?;
+        }
+        store.sync().map_err(io_error)?;
+
+        let mut meta = Meta::new(Page::new(0));
+        meta.set_root_page_id(page_ids.first().cloned().unwrap_or(0));
+        meta.set_prefix_compression(self.options.prefix_compression);
+        meta.set_pointer_format(self.options.pointer_format);
+        meta.set_comparator_name(self.comparator.name);
+        meta.set_max_key_size(self.max_key_size.load(Ordering::SeqCst));
+        meta.set_free_list_head(free_list_head);
+        store.write_page(0, &meta.page.bytes).map_err(io_error)?;
+        store.sync().map_err(io_error)?;
+        Ok(page_ids.len())
+    }
+}
@@ -0,0 +1,143 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt::Debug;
+
+use crate::btree::BTree;
+use crate::node::Node;
+use crate::page::Page;
+use crate::slot::SlotBytes;
+use crate::slotted::PageDamage;
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    Damaged { page_id: u16, damage: PageDamage },
+    // the store failed to read the page
+    Unreadable { page_id: u16, error: String },
+    KeysOutOfOrder { page_id: u16, index: usize },
+    // the key at index is outside the separators the parent keeps for the page
+    OutsideSeparators { page_id: u16, index: usize },
+    UnevenDepth { page_id: u16, depth: usize },
+    // zero, the meta page or past the end of the file
+    BadChild { page_id: u16, child_page_id: u16 },
+    ReachedTwice { page_id: u16 },
+    Freed { page_id: u16 },
+    Leaked { page_id: u16 },
+    // leaks are not looked for without the free list
+    FreeListUnreadable { error: String },
+}
+
+#[derive(Default)]
+struct Walk {
+    reached: HashSet<u16>,
+    leaf_depth: Option<usize>,
+    problems: Vec<Problem>,
+}
+
+impl<K, V> BTree<K, V>
+    where K: SlotBytes + Debug,
+          V: SlotBytes + Clone + Debug,
+{
+    // leaks are only looked for when the tree has the whole file, a tree in a Db shares it with others
+    pub fn check(&self) -> Vec<Problem> {
+        let _latch = self.latch.write().unwrap();
+        let mut walk = Walk::default();
+        let root_page_id = match self.read_meta() {
+            Ok(meta) => meta.root_page_id(),
+            Err(error) => return vec![Problem::Unreadable { page_id: self.meta_page_id, error: error.to_string() }],
+        };
+        if root_page_id != 0 && self.valid_child(self.meta_page_id, root_page_id, &mut walk) {
+            self.check_page(root_page_id, 0, None, None, &mut walk);
+        }
+
+        let free_page_ids = match self.storage.free_page_ids() {
+            Ok(free_page_ids) => free_page_ids,
+            Err(error) => {
+                walk.problems.push(Problem::FreeListUnreadable { error: error.to_string() });
+                return walk.problems;
+            }
+        };
+        for page_id in &free_page_ids {
+            if walk.reached.contains(page_id) {
+                walk.problems.push(Problem::Freed { page_id: *page_id });
+            }
+        }
+        if self.meta_page_id == 0 {
+            for page_id in 1..self.storage.next_page_id() {
+                if !walk.reached.contains(&page_id) && !free_page_ids.contains(&page_id) {
+                    walk.problems.push(Problem::Leaked { page_id });
+                }
+            }
+        }
+        walk.problems
+    }
+
+    // every key of the page is in lower..upper
+    fn check_page(&self, page_id: u16, depth: usize, lower: Option<&K>, upper: Option<&K>, walk: &mut Walk) {
+        if !walk.reached.insert(page_id) {
+            walk.problems.push(Problem::ReachedTwice { page_id });
+            return;
+        }
+        let mut page = Page::new(page_id);
+        if let Err(error) = self.storage.read_page(&mut page) {
+            walk.problems.push(Problem::Unreadable { page_id, error: error.to_string() });
+            return;
+        }
+        match Node::<K, V>::new(page, self.comparator.compare) {
+            Node::Leaf(leaf) => {
+                if let Some(damage) = leaf.slotted.damage() {
+                    walk.problems.push(Problem::Damaged { page_id, damage });
+                    return;
+                }
+                self.check_keys(page_id, &leaf.slotted.keys(), lower, upper, walk);
+                match walk.leaf_depth {
+                    Some(leaf_depth) if leaf_depth != depth => walk.problems.push(Problem::UnevenDepth { page_id, depth }),
+                    Some(_) => {},
+                    None => walk.leaf_depth = Some(depth),
+                }
+            },
+            Node::Branch(branch) => {
+                if let Some(damage) = branch.slotted.damage() {
+                    walk.problems.push(Problem::Damaged { page_id, damage });
+                    return;
+                }
+                let (keys, child_page_ids): (Vec<K>, Vec<u16>) = branch.slotted.slots().into_iter().unzip();
+                self.check_keys(page_id, &keys, lower, upper, walk);
+                // child i holds the keys from the previous separator up to its own
+                for (index, child_page_id) in child_page_ids.into_iter().enumerate() {
+                    if self.valid_child(page_id, child_page_id, walk) {
+                        let child_lower = if index == 0 { lower } else { Some(&keys[index - 1]) };
+                        self.check_page(child_page_id, depth + 1, child_lower, Some(&keys[index]), walk);
+                    }
+                }
+                if self.valid_child(page_id, branch.max_page_id(), walk) {
+                    self.check_page(branch.max_page_id(), depth + 1, keys.last().or(lower), upper, walk);
+                }
+            },
+        }
+    }
+
+    fn check_keys(&self, page_id: u16, keys: &[K], lower: Option<&K>, upper: Option<&K>, walk: &mut Walk) {
+        let compare = self.comparator.compare;
+        for (index, key) in keys.iter().enumerate() {
+            if index > 0 && compare(&keys[index - 1], key) != Ordering::Less {
+                walk.problems.push(Problem::KeysOutOfOrder { page_id, index });
+            }
+            let below_lower = lower.is_some_and(|lower| compare(key, lower) == Ordering::Less);
+            let from_upper = upper.is_some_and(|upper| compare(key, upper) != Ordering::Less);
+            if below_lower || from_upper {
+                walk.problems.push(Problem::OutsideSeparators { page_id, index });
+            }
+        }
+    }
+
+    fn valid_child(&self, page_id: u16, child_page_id: u16, walk: &mut Walk) -> bool {
+        let valid = child_page_id != 0
+            && child_page_id != self.meta_page_id
+            && child_page_id < self.storage.next_page_id();
+        if !valid {
+            walk.problems.push(Problem::BadChild { page_id, child_page_id });
+        }
+        valid
+    }
+}
@@ -0,0 +1,76 @@
+use std::fmt::Debug;
+use std::fmt::Write;
+
+use crate::btree::BTree;
+use crate::node::Node;
+use crate::page::PAGE_SIZE;
+use crate::page::Page;
+use crate::slot::SlotBytes;
+
+
+impl<K, V> BTree<K, V>
+    where K: SlotBytes + Debug,
+          V: SlotBytes + Clone + Debug,
+{
+    pub fn to_dot(&self) -> String {
+        self.to_dot_with(false)
+    }
+
+    // siblings draws dashed links between neighbouring leaves, leaves keep no pointers of their own
+    pub fn to_dot_with(&self, siblings: bool) -> String {
+        let _latch = self.latch.write().unwrap();
+        let mut dot = String::from("digraph btree {\n    node [shape=box, fontname=\"monospace\"];\n");
+        let mut leaf_page_ids = vec![];
+        if let Some(root_page_id) = self.root_page_id() {
+            self.dot_internal(root_page_id, &mut dot, &mut leaf_page_ids);
+        }
+        if siblings {
+            for pair in leaf_page_ids.windows(2) {
+                let _ = writeln!(dot, "    p{} -> p{} [style=dashed, constraint=false];", pair[0], pair[1]);
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn dot_internal(&self, page_id: u16, dot: &mut String, leaf_page_ids: &mut Vec<u16>) {
+        let mut page = Page::new(page_id);
+        // a page the store fails to read is drawn without its keys
+        if self.storage.read_page(&mut page).is_err() {
+            let _ = writeln!(dot, "    p{} [label=\"unreadable {}\"];", page_id, page_id);
+            return;
+        }
+        match Node::<K, V>::new(page, self.comparator.compare) {
+            Node::Leaf(leaf) => {
+                let fill = fill(leaf.slotted.free_space(), leaf.slotted.fragmented_bytes());
+                write_node(dot, page_id, "leaf", fill, leaf.slotted.keys());
+                leaf_page_ids.push(page_id);
+            },
+            Node::Branch(branch) => {
+                let fill = fill(branch.slotted.free_space(), branch.slotted.fragmented_bytes());
+                let (keys, mut child_page_ids): (Vec<K>, Vec<u16>) = branch.slotted.slots().into_iter().unzip();
+                write_node(dot, page_id, "branch", fill, keys);
+                child_page_ids.push(branch.max_page_id());
+                for child_page_id in child_page_ids {
+                    let _ = writeln!(dot, "    p{} -> p{};", page_id, child_page_id);
+                    self.dot_internal(child_page_id, dot, leaf_page_ids);
+                }
+            },
+        }
+    }
+}
+
+// percent of the page in use, headers and pointers included
+fn fill(free_space: usize, fragmented_bytes: u16) -> usize {
+    100 * (PAGE_SIZE - free_space - fragmented_bytes as usize) / PAGE_SIZE
+}
+
+fn write_node<K: Debug>(dot: &mut String, page_id: u16, kind: &str, fill: usize, keys: Vec<K>) {
+    let keys = keys.iter().map(|key| format!("{:?}", key)).collect::<Vec<_>>().join(" ");
+    let label = format!("{} {}\n{}% full\n{}", kind, page_id, fill, keys);
+    let _ = writeln!(dot, "    p{} [label=\"{}\"];", page_id, escape(&label));
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
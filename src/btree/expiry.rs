@@ -0,0 +1,134 @@
+use std::fmt::Debug;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::btree::BTree;
+use crate::error::Error;
+use crate::node::Node;
+use crate::slot::SlotBytes;
+use crate::store::io_error;
+
+
+// a value with the time it expires, stored in front of it in the leaf slot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expiring<V> {
+    pub value: V,
+    // milliseconds since the unix epoch, 0 for never
+    pub expires_at: u64,
+}
+
+impl<V> Expiring<V> {
+    pub fn new(value: V) -> Self {
+        Expiring { value, expires_at: 0 }
+    }
+
+    pub fn with_ttl(value: V, ttl: Duration) -> Self {
+        Expiring { value, expires_at: now().saturating_add(ttl.as_millis() as u64) }
+    }
+}
+
+impl<V: SlotBytes> SlotBytes for Expiring<V> {
+    fn into_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.expires_at.to_le_bytes().to_vec();
+        bytes.extend(self.value.into_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let mut expires_at = [0; 8];
+        expires_at.copy_from_slice(&bytes[..8]);
+        Expiring { value: V::from_bytes(&bytes[8..]), expires_at: u64::from_le_bytes(expires_at) }
+    }
+
+    fn decodes(bytes: &[u8]) -> bool {
+        bytes.len() >= 8 && V::decodes(&bytes[8..])
+    }
+
+    fn expires_at(&self) -> Option<u64> {
+        match self.expires_at {
+            0 => None,
+            expires_at => Some(expires_at),
+        }
+    }
+}
+
+impl<K, V> BTree<K, Expiring<V>>
+    where K: SlotBytes + Clone + Debug,
+          V: SlotBytes + Clone + Debug,
+{
+    // replaces the entry of the key, expired or not, as one write under the tree latch
+    pub fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) -> Result<(), Error> {
+        let value = Expiring::with_ttl(value, ttl);
+        self.check_fits(&key, &value)?;
+        let _latch = self.latch.write().unwrap();
+        let _writing = self.storage.begin_write();
+        match self.root_page_id() {
+            Some(root_page_id) => match self.update_internal(root_page_id, &key, value.clone(), true) {
+                Err(Error::NotFound) => self.insert_from_root(key, value).map_err(io_error),
+                result => result,
+            },
+            None => self.create_root(key, value).map_err(io_error),
+        }
+    }
+}
+
+impl<K, V> BTree<K, V>
+    where K: SlotBytes + Debug,
+          V: SlotBytes + Clone + Debug,
+{
+    // searches and scans already skip expired entries, this deletes them; returns how many
+    pub fn purge_expired(&self) -> usize {
+        let _latch = self.latch.write().unwrap();
+        let _writing = self.storage.begin_write();
+        match self.root_page_id() {
+            Some(root_page_id) => self.purge_internal(root_page_id, now()),
+            None => 0,
+        }
+    }
+
+    // deletes never merge pages, so leaves are edited where they are
+    fn purge_internal(&self, page_id: u16, now: u64) -> usize {
+        let node = match self.read_node(page_id) {
+            Ok(node) => node,
+            Err(error) => {
+                let _ = self.storage.keep_error(Err(error));
+                return 0;
+            }
+        };
+        match node {
+            Node::Leaf(mut leaf) => {
+                let expired = leaf.slotted.slots().into_iter()
+                    .filter(|(_, value)| expired(value, now))
+                    .map(|(key, _)| key)
+                    .collect::<Vec<_>>();
+                for key in &expired {
+                    let _ = leaf.slotted.delete(key);
+                }
+                // a read or write that fails is returned by flush
+                if !expired.is_empty() {
+                    let _ = self.write_leaf(&mut leaf);
+                }
+                expired.len()
+            },
+            Node::Branch(branch) => {
+                let mut child_page_ids = branch.slotted.slots().into_iter().map(|(_, page_id)| page_id).collect::<Vec<_>>();
+                child_page_ids.push(branch.max_page_id());
+                child_page_ids.into_iter().map(|child_page_id| self.purge_internal(child_page_id, now)).sum()
+            },
+        }
+    }
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as u64)
+}
+
+pub(crate) fn expired(value: &impl SlotBytes, now: u64) -> bool {
+    value.expires_at().is_some_and(|expires_at| expires_at <= now)
+}
+
+// reads the clock only for a value that expires at all
+pub(crate) fn expired_now(value: &impl SlotBytes) -> bool {
+    value.expires_at().is_some_and(|expires_at| expires_at <= now())
+}
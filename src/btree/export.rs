@@ -0,0 +1,83 @@
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::io::Read;
+use std::io::Write;
+
+use crate::btree::BTree;
+use crate::comparator::Compare;
+use crate::error::Error;
+use crate::slot::SlotBytes;
+use crate::store::io_error;
+use crate::text::ExportFormat;
+use crate::text::SlotText;
+
+
+impl<K, V> BTree<K, V>
+    where K: SlotBytes + SlotText + Clone + Debug,
+          V: SlotBytes + SlotText + Clone + Debug,
+{
+    // every entry of one snapshot in key order, returns how many were written
+    pub fn export(&self, mut writer: impl Write, format: ExportFormat) -> Result<usize, Error> {
+        let slots = self.snapshot().try_range(..).map_err(io_error)?;
+        for (key, value) in &slots {
+            let record = format.record(&key.to_text(), &value.to_text());
+            writer.write_all(record.as_bytes()).map_err(io_error)?;
+        }
+        writer.flush().map_err(io_error)?;
+        Ok(slots.len())
+    }
+
+    // the whole input is parsed before the first write, then merged with the entries already there and
+    // the tree rebuilt bottom-up on full leaves, as vacuum does; existing keys get the imported value
+    pub fn import(&self, mut reader: impl Read, format: ExportFormat) -> Result<usize, Error> {
+        let mut input = String::new();
+        reader.read_to_string(&mut input).map_err(io_error)?;
+        let records = format.parse(&input).map_err(|(line, message)| Error::Parse(line, message))?;
+        let mut slots = Vec::with_capacity(records.len());
+        for (line, key, value) in records {
+            let key = K::from_text(&key).map_err(|message| Error::Parse(line, message))?;
+            let value = V::from_text(&value).map_err(|message| Error::Parse(line, message))?;
+            self.check_fits(&key, &value).map_err(|_| Error::Parse(line, "the entry does not fit in a page".to_string()))?;
+            slots.push((key, value));
+        }
+        let count = slots.len();
+        let compare = self.comparator.compare;
+        // stable, so of equal keys the last line wins
+        slots.sort_by(|(a, _), (b, _)| compare(a, b));
+        let mut imported: Vec<(K, V)> = Vec::with_capacity(slots.len());
+        for (key, value) in slots {
+            match imported.last_mut() {
+                Some(last) if compare(&last.0, &key) == Ordering::Equal => *last = (key, value),
+                _ => imported.push((key, value)),
+            }
+        }
+
+        let _latch = self.latch.write().unwrap();
+        let _writing = self.storage.begin_write();
+        for (key, _) in &imported {
+            self.grow_max_key_size(key).map_err(io_error)?;
+        }
+        self.rebuild(|existing| merge(existing, imported, compare), 1.0).map_err(io_error)?;
+        Ok(count)
+    }
+}
+
+// both in key order, the imported entry of a key replaces the existing one
+fn merge<K, V>(existing: Vec<(K, V)>, imported: Vec<(K, V)>, compare: Compare<K>) -> Vec<(K, V)> {
+    let mut merged = Vec::with_capacity(existing.len() + imported.len());
+    let mut existing = existing.into_iter().peekable();
+    let mut imported = imported.into_iter().peekable();
+    while let (Some((a, _)), Some((b, _))) = (existing.peek(), imported.peek()) {
+        match compare(a, b) {
+            Ordering::Less => merged.extend(existing.next()),
+            Ordering::Equal => {
+                existing.next();
+                merged.extend(imported.next());
+            },
+            Ordering::Greater => merged.extend(imported.next()),
+        }
+    }
+    merged.extend(existing);
+    merged.extend(imported);
+    merged
+}
@@ -11,16 +11,17 @@ use crate::slot::SlotBytes;
 
 
 impl<K, V> Debug for BTree<K, V>
-    where K: Ord + SlotBytes + Debug,
+    where K: SlotBytes + Debug,
           V: SlotBytes + Debug,
 {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         // let _ = writeln!(f, "{:?}", self.root_page_id);
-        if let Some(root_page_id) = self.root_page_id {
-            let mut meta_page = Page::new(0);
-            self.storage.borrow_mut().read_page(&mut meta_page);
+        let _latch = self.latch.read().unwrap();
+        if let Some(root_page_id) = self.root_page_id() {
+            let mut meta_page = Page::new(self.meta_page_id);
+            self.storage.read_page(&mut meta_page).map_err(|_| Error)?;
             let meta = Meta::new(meta_page);
-            let _ = writeln!(f, "MT(0): {:?}", meta);
+            let _ = writeln!(f, "MT({}): {:?}", self.meta_page_id, meta);
 
             self.fmt_internal(f, root_page_id)
         } else {
@@ -30,13 +31,13 @@ impl<K, V> Debug for BTree<K, V>
 }
 
 impl<K, V> BTree<K, V>
-    where K: Ord + SlotBytes + Debug,
+    where K: SlotBytes + Debug,
           V: SlotBytes + Debug,
 {
     fn fmt_internal(&self, f: &mut Formatter<'_>, page_id: u16) -> Result<(), Error> {
         let mut page = Page::new(page_id);
-        self.storage.borrow_mut().read_page(&mut page);
-        let node: Node<K, V> = Node::new(page);
+        self.storage.read_page(&mut page).map_err(|_| Error)?;
+        let node: Node<K, V> = Node::new(page, self.comparator.compare);
         match node {
             Node::Leaf(leaf) => {
                 writeln!(f, "LF{:?}", leaf)
@@ -0,0 +1,125 @@
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::io::Write;
+use std::ops::RangeBounds;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::btree::BTree;
+use crate::btree::PageInfo;
+use crate::btree::Problem;
+use crate::btree::Snapshot;
+use crate::btree::Stats;
+use crate::comparator::Comparator;
+use crate::error::Error;
+use crate::options::Durability;
+use crate::options::Options;
+use crate::slot::SlotBytes;
+use crate::storage::Storage;
+use crate::store::FileStore;
+use crate::store::io_error;
+use crate::text::ExportFormat;
+use crate::text::SlotText;
+
+
+// a tree opened without write permission or lent out by Indexed, it has no method that writes
+pub struct ReadOnlyBTree<K, V> {
+    pub(crate) btree: BTree<K, V>,
+}
+
+impl<K, V> BTree<K, V>
+    where K: Ord + SlotBytes + Clone + Debug,
+          V: SlotBytes + Clone + Debug,
+{
+    pub fn open_read_only(file_path: impl AsRef<Path>) -> Result<ReadOnlyBTree<K, V>, Error> {
+        Self::open_read_only_with(file_path, Comparator::natural())
+    }
+}
+
+impl<K, V> BTree<K, V>
+    where K: SlotBytes + Debug,
+          V: SlotBytes + Clone + Debug,
+{
+    // never creates the file, an empty one has no tree to read
+    pub fn open_read_only_with(file_path: impl AsRef<Path>, comparator: Comparator<K>) -> Result<ReadOnlyBTree<K, V>, Error> {
+        let storage = Arc::new(Storage::new(Box::new(FileStore::open_read_only(file_path)?), Durability::Never).map_err(io_error)?);
+        if storage.next_page_id() == 0 {
+            return Err(Error::NoPage);
+        }
+        let btree = Self::open_in(storage, 0, Options::default(), comparator)?;
+        Ok(ReadOnlyBTree { btree })
+    }
+}
+
+impl<K, V> ReadOnlyBTree<K, V> {
+    pub(crate) fn new(btree: BTree<K, V>) -> Self {
+        ReadOnlyBTree { btree }
+    }
+
+    pub fn meta_page_id(&self) -> u16 {
+        self.btree.meta_page_id()
+    }
+
+    pub fn root_page_id(&self) -> Option<u16> {
+        self.btree.root_page_id()
+    }
+}
+
+impl<K, V> ReadOnlyBTree<K, V>
+    where K: SlotBytes + Debug,
+          V: SlotBytes + Clone + Debug,
+{
+    pub fn search(&self, key: &K) -> Result<V, Error> {
+        self.btree.search(key)
+    }
+
+    pub fn snapshot(&self) -> Snapshot<'_, K, V> {
+        self.btree.snapshot()
+    }
+
+    pub fn range(&self, range: impl RangeBounds<K>) -> Vec<(K, V)> {
+        self.btree.range(range)
+    }
+
+    pub fn pages(&self) -> Vec<PageInfo> {
+        self.btree.pages()
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.btree.stats()
+    }
+
+    pub fn backup_to(&self, file_path: impl AsRef<Path>) -> Result<usize, Error> {
+        self.btree.backup_to(file_path)
+    }
+
+    pub fn check(&self) -> Vec<Problem> {
+        self.btree.check()
+    }
+
+    pub fn to_dot(&self) -> String {
+        self.btree.to_dot()
+    }
+
+    pub fn to_dot_with(&self, siblings: bool) -> String {
+        self.btree.to_dot_with(siblings)
+    }
+}
+
+impl<K, V> ReadOnlyBTree<K, V>
+    where K: SlotBytes + SlotText + Clone + Debug,
+          V: SlotBytes + SlotText + Clone + Debug,
+{
+    pub fn export(&self, writer: impl Write, format: ExportFormat) -> Result<usize, Error> {
+        self.btree.export(writer, format)
+    }
+}
+
+impl<K, V> Debug for ReadOnlyBTree<K, V>
+    where K: SlotBytes + Debug,
+          V: SlotBytes + Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.btree.fmt(f)
+    }
+}
@@ -0,0 +1,198 @@
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::io;
+use std::ops::Bound;
+use std::ops::RangeBounds;
+
+use crate::btree::BTree;
+use crate::btree::expiry;
+use crate::error::Error;
+use crate::node::Node;
+use crate::page::PAGE_SIZE;
+use crate::page::Page;
+use crate::slot::SlotBytes;
+use crate::store::io_error;
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageInfo {
+    pub page_id: u16,
+    pub branch: bool,
+    // the root is at depth 0
+    pub depth: usize,
+    pub keys: usize,
+    pub free_space: usize,
+    pub fragmented_bytes: usize,
+}
+
+impl PageInfo {
+    // share of the page in use, headers and pointers included
+    pub fn fill(&self) -> f64 {
+        (PAGE_SIZE - self.free_space - self.fragmented_bytes) as f64 / PAGE_SIZE as f64
+    }
+}
+
+pub struct Snapshot<'a, K, V> {
+    btree: &'a BTree<K, V>,
+    version: u64,
+    root_page_id: Option<u16>,
+    // entries expire as of when the snapshot was taken
+    now: u64,
+}
+
+impl<'a, K, V> Snapshot<'a, K, V>
+    where K: SlotBytes + Debug,
+          V: SlotBytes + Clone + Debug,
+{
+    pub fn new(btree: &'a BTree<K, V>, version: u64, root_page_id: Option<u16>) -> Self {
+        btree.storage.pin(version);
+        Snapshot { btree, version, root_page_id, now: expiry::now() }
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn search(&self, key: &K) -> Result<V, Error> {
+        let mut page_id = self.root_page_id.ok_or(Error::NoPage)?;
+        loop {
+            match self.read_node(page_id).map_err(io_error)? {
+                Node::Leaf(leaf) => {
+                    return leaf.slotted.search(key).filter(|value| !expiry::expired(value, self.now)).ok_or(Error::NotFound);
+                },
+                Node::Branch(branch) => page_id = branch.child_page_id(key),
+            }
+        }
+    }
+
+    // a page that cannot be read panics, check reports it instead
+    pub fn range(&self, range: impl RangeBounds<K>) -> Vec<(K, V)> {
+        self.try_range(range).unwrap_or_else(|error| panic!("{:?}: the range could not be read", error))
+    }
+
+    // every page reachable from the root, parents before their children
+    pub fn pages(&self) -> Vec<PageInfo> {
+        self.try_pages().unwrap_or_else(|error| panic!("{:?}: the pages could not be read", error))
+    }
+
+    pub(crate) fn try_range(&self, range: impl RangeBounds<K>) -> io::Result<Vec<(K, V)>> {
+        let mut slots = vec![];
+        if let Some(root_page_id) = self.root_page_id {
+            self.range_internal(root_page_id, &range, &mut slots)?;
+        }
+        Ok(slots)
+    }
+
+    pub(crate) fn try_pages(&self) -> io::Result<Vec<PageInfo>> {
+        let mut pages = vec![];
+        if let Some(root_page_id) = self.root_page_id {
+            self.pages_internal(root_page_id, 0, &mut pages)?;
+        }
+        Ok(pages)
+    }
+
+    fn pages_internal(&self, page_id: u16, depth: usize, pages: &mut Vec<PageInfo>) -> io::Result<()> {
+        match self.read_node(page_id)? {
+            Node::Leaf(leaf) => pages.push(PageInfo {
+                page_id,
+                branch: false,
+                depth,
+                keys: leaf.slotted.keys().len(),
+                free_space: leaf.slotted.free_space(),
+                fragmented_bytes: leaf.slotted.fragmented_bytes() as usize,
+            }),
+            Node::Branch(branch) => {
+                let slots = branch.slotted.slots();
+                pages.push(PageInfo {
+                    page_id,
+                    branch: true,
+                    depth,
+                    keys: slots.len(),
+                    free_space: branch.slotted.free_space(),
+                    fragmented_bytes: branch.slotted.fragmented_bytes() as usize,
+                });
+                for (_, child_page_id) in slots {
+                    self.pages_internal(child_page_id, depth + 1, pages)?;
+                }
+                self.pages_internal(branch.max_page_id(), depth + 1, pages)?;
+            },
+        }
+        Ok(())
+    }
+
+    fn range_internal(&self, page_id: u16, range: &impl RangeBounds<K>, slots: &mut Vec<(K, V)>) -> io::Result<()> {
+        match self.read_node(page_id)? {
+            Node::Leaf(leaf) => {
+                let in_range = leaf.slotted.slots().into_iter()
+                    .filter(|(_, v)| !expiry::expired(v, self.now))
+                    .filter(|(k, _)| self.after_start(k, range.start_bound()) && self.before_end(k, range.end_bound()));
+                slots.extend(in_range);
+            },
+            Node::Branch(branch) => {
+                // child i holds the keys from the previous separator up to its own
+                let mut lower: Option<K> = None;
+                for (upper, child_page_id) in branch.slotted.slots() {
+                    if !self.below(&upper, range.start_bound()) && self.starts_in(&lower, range.end_bound()) {
+                        self.range_internal(child_page_id, range, slots)?;
+                    }
+                    lower = Some(upper);
+                }
+                if self.starts_in(&lower, range.end_bound()) {
+                    self.range_internal(branch.max_page_id(), range, slots)?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn after_start(&self, key: &K, start: Bound<&K>) -> bool {
+        match start {
+            Bound::Included(start) => self.compare(key, start) != Ordering::Less,
+            Bound::Excluded(start) => self.compare(key, start) == Ordering::Greater,
+            Bound::Unbounded => true,
+        }
+    }
+
+    fn before_end(&self, key: &K, end: Bound<&K>) -> bool {
+        match end {
+            Bound::Included(end) => self.compare(key, end) != Ordering::Greater,
+            Bound::Excluded(end) => self.compare(key, end) == Ordering::Less,
+            Bound::Unbounded => true,
+        }
+    }
+
+    // every key below upper is before the range
+    fn below(&self, upper: &K, start: Bound<&K>) -> bool {
+        match start {
+            Bound::Included(start) | Bound::Excluded(start) => self.compare(upper, start) != Ordering::Greater,
+            Bound::Unbounded => false,
+        }
+    }
+
+    fn starts_in(&self, lower: &Option<K>, end: Bound<&K>) -> bool {
+        match lower {
+            Some(lower) => self.before_end(lower, end),
+            None => true,
+        }
+    }
+
+    fn compare(&self, a: &K, b: &K) -> Ordering {
+        (self.btree.comparator.compare)(a, b)
+    }
+
+    pub(crate) fn read_page(&self, page_id: u16) -> io::Result<Page> {
+        let mut page = Page::new(page_id);
+        self.btree.storage.read_page_at(&mut page, self.version)?;
+        Ok(page)
+    }
+
+    fn read_node(&self, page_id: u16) -> io::Result<Node<K, V>> {
+        Ok(Node::new(self.read_page(page_id)?, self.btree.comparator.compare))
+    }
+}
+
+impl<K, V> Drop for Snapshot<'_, K, V> {
+    fn drop(&mut self) {
+        self.btree.storage.unpin(self.version);
+    }
+}
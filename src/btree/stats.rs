@@ -0,0 +1,51 @@
+use std::fmt::Debug;
+
+use crate::btree::BTree;
+use crate::slot::SlotBytes;
+
+
+// fills are shares of a page; values always fit in a leaf, so there are no overflow pages to count
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Stats {
+    pub height: usize,
+    pub branch_pages: usize,
+    pub leaf_pages: usize,
+    pub keys: usize,
+    pub min_fill: f64,
+    pub max_fill: f64,
+    pub average_fill: f64,
+    pub free_space: usize,
+    pub fragmented_bytes: usize,
+    // the free list is shared by every tree in the file
+    pub free_pages: usize,
+}
+
+impl<K, V> BTree<K, V>
+    where K: SlotBytes + Debug,
+          V: SlotBytes + Clone + Debug,
+{
+    pub fn stats(&self) -> Stats {
+        let pages = self.pages();
+        let free_page_ids = self.storage.free_page_ids().unwrap_or_else(|error| panic!("{:?}: the free list could not be read", error));
+        let mut stats = Stats { free_pages: free_page_ids.len(), ..Default::default() };
+        if pages.is_empty() {
+            return stats;
+        }
+        let fills = pages.iter().map(|page| page.fill()).collect::<Vec<_>>();
+        stats.min_fill = fills.iter().cloned().fold(f64::MAX, f64::min);
+        stats.max_fill = fills.iter().cloned().fold(0.0, f64::max);
+        stats.average_fill = fills.iter().sum::<f64>() / fills.len() as f64;
+        for page in pages {
+            stats.height = stats.height.max(page.depth + 1);
+            if page.branch {
+                stats.branch_pages += 1;
+            } else {
+                stats.leaf_pages += 1;
+                stats.keys += page.keys;
+            }
+            stats.free_space += page.free_space;
+            stats.fragmented_bytes += page.fragmented_bytes;
+        }
+        stats
+    }
+}
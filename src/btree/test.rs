@@ -1,16 +1,47 @@
+use std::collections::BTreeMap;
 use std::fs::File;
 // use std::fs::OpenOptions;
 use std::fs::remove_file;
+use std::io;
+use std::ops::Bound;
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
 
 // use std::io::Read;
 
 // use std::path::Path;
 
 use crate::btree::BTree;
+use crate::btree::Problem;
+use crate::btree::Expiring;
+use crate::btree::Stats;
+use crate::comparator::Comparator;
 use crate::error::Error;
 use crate::node::Node;
-// use crate::page::PAGE_SIZE;
+use crate::options::Durability;
+use crate::options::Options;
+use crate::options::PointerFormat;
+#[cfg(feature = "compression")]
+use crate::codec::Codec;
+#[cfg(feature = "compression")]
+use crate::codec::Lz;
+#[cfg(feature = "compression")]
+use crate::store::CompressedStore;
+use crate::store::EncryptedStore;
+use crate::store::FileStore;
+use crate::store::MemoryStore;
+#[cfg(all(any(target_os = "linux", target_os = "macos"), target_pointer_width = "64"))]
+use crate::store::MmapStore;
+use crate::store::PageStore;
+use crate::text::ExportFormat;
+use crate::page::Page;
+use crate::page::PAGE_SIZE;
 use crate::slot::Slot;
+use crate::slot::SlotBytes;
+use crate::slotted::PageDamage;
 
 
 #[test]
@@ -27,7 +58,7 @@ fn test_search_empty() {
 //     let key = 123u8;
 //     let value = "abc".to_string();
 //     let p = "test_insert_first";
-//     let mut btree = BTree::create(p);
+//     let btree = BTree::create(p);
 //     let value_len = value.len();
 //     btree.insert(key, value);
 //     let mut f = OpenOptions::new()
@@ -48,7 +79,7 @@ fn test_search_empty() {
 // #[test]
 // fn test_insert_multi() {
 //     let p = "test_insert_multi";
-//     let mut btree = BTree::create(p);
+//     let btree = BTree::create(p);
 //     btree.insert(13u16, "abc".to_string());
 //     btree.insert(8976u16, "ありがと".to_string());
 //     let res = file_bytes(p);
@@ -59,17 +90,17 @@ fn test_search_empty() {
 #[test]
 fn test_insert_split() {
     let p = "test_insert_split";
-    let mut btree = BTree::create(p);
-    btree.insert(22u16, "abc".to_string());
-    btree.insert(55u16, "defg".to_string());
-    btree.insert(33u16, "あ".to_string());
-    btree.insert(66u16, "い".to_string());
-    btree.insert(11u16, "ぽ".to_string());
-
-    match btree.read_node(btree.root_page_id.unwrap()) {
+    let btree = BTree::create(p);
+    btree.insert(22u16, "abc".to_string()).unwrap();
+    btree.insert(55u16, "defg".to_string()).unwrap();
+    btree.insert(33u16, "あ".to_string()).unwrap();
+    btree.insert(66u16, "い".to_string()).unwrap();
+    btree.insert(11u16, "ぽ".to_string()).unwrap();
+
+    match btree.read_node(btree.root_page_id().unwrap()).unwrap() {
         Node::Leaf(mut leaf) => {
             let mut breadcrumb = vec![];
-            btree.split(&mut leaf.slotted, Slot::new(44u16, "あふれちゃう".to_string()), &mut breadcrumb);
+            let _ = btree.split(&mut leaf.slotted, Slot::new(44u16, "あふれちゃう".to_string()), &mut breadcrumb);
         },
         Node::Branch(_) => panic!(""),
     }
@@ -82,12 +113,12 @@ fn test_insert_split() {
 #[test]
 fn test_search_split() {
     let p = "test_search_split";
-    let mut btree = BTree::create(p);
-    btree.insert(22u16, "abc".to_string());
-    btree.insert(55u16, "defg".to_string());
-    btree.insert(33u16, "あ".to_string());
-    btree.insert(66u16, "い".to_string());
-    btree.insert(44u16, "あふれちゃう".to_string());
+    let btree = BTree::create(p);
+    btree.insert(22u16, "abc".to_string()).unwrap();
+    btree.insert(55u16, "defg".to_string()).unwrap();
+    btree.insert(33u16, "あ".to_string()).unwrap();
+    btree.insert(66u16, "い".to_string()).unwrap();
+    btree.insert(44u16, "あふれちゃう".to_string()).unwrap();
     // btree.insert(35u16, "add".to_string());
     println!("{:?}", btree);
 
@@ -99,8 +130,8 @@ fn test_search_split() {
 #[test]
 fn test_insert_meta() {
     let p = "test_insert_meta";
-    let mut btree = BTree::<u16, String>::create(p);
-    btree.insert(22u16, "abc".to_string());
+    let btree = BTree::<u16, String>::create(p);
+    btree.insert(22u16, "abc".to_string()).unwrap();
 
     println!("{:?}", btree);
 
@@ -110,14 +141,15 @@ fn test_insert_meta() {
 #[test]
 fn test_read_meta() {
     let p = "sample/test_read_meta";
-    let mut btree = BTree::<u16, String>::create(p);
-    btree.insert(22u16, "abc".to_string());
-    btree.insert(55u16, "defg".to_string());
-    btree.insert(33u16, "あ".to_string());
-    btree.insert(66u16, "い".to_string());
-    btree.insert(44u16, "あふれちゃう".to_string());
+    let btree = BTree::<u16, String>::create(p);
+    btree.insert(22u16, "abc".to_string()).unwrap();
+    btree.insert(55u16, "defg".to_string()).unwrap();
+    btree.insert(33u16, "あ".to_string()).unwrap();
+    btree.insert(66u16, "い".to_string()).unwrap();
+    btree.insert(44u16, "あふれちゃう".to_string()).unwrap();
     println!("{:?}", btree);
 
+    drop(btree);
     let btree = BTree::<u16, String>::create(p);
     println!("{:?}", btree);
 
@@ -128,19 +160,19 @@ fn test_read_meta() {
 fn test_split_multi() {
     let p = "sample/test_split_multi";
     if File::open(p).is_err() {
-        let mut btree = BTree::<u16, String>::create(p);
-        btree.insert(22u16, "abc".to_string());
-        btree.insert(55u16, "defg".to_string());
-        btree.insert(33u16, "あ".to_string());
-        btree.insert(66u16, "い".to_string());
-        btree.insert(44u16, "あふれちゃう".to_string());
-        btree.insert(35u16, "add".to_string());    
+        let btree = BTree::<u16, String>::create(p);
+        btree.insert(22u16, "abc".to_string()).unwrap();
+        btree.insert(55u16, "defg".to_string()).unwrap();
+        btree.insert(33u16, "あ".to_string()).unwrap();
+        btree.insert(66u16, "い".to_string()).unwrap();
+        btree.insert(44u16, "あふれちゃう".to_string()).unwrap();
+        btree.insert(35u16, "add".to_string()).unwrap();
     }
 
-    let mut btree = BTree::<u16, String>::create(p);
+    let btree = BTree::<u16, String>::create(p);
     println!("{:?}", btree);
     if btree.search(&58).is_err() {
-        btree.insert(58u16, "i am 58".to_string());
+        btree.insert(58u16, "i am 58".to_string()).unwrap();
     }
 
     assert_eq!(btree.search(&33), Ok("あ".to_string()));
@@ -150,22 +182,22 @@ fn test_split_multi() {
 fn test_split_nested() {
     let p = "sample/test_split_nested";
     if File::open(p).is_err() {
-        let mut btree = BTree::<u16, String>::create(p);
-        btree.insert(22u16, "abc".to_string());
-        btree.insert(55u16, "defg".to_string());
-        btree.insert(33u16, "あ".to_string());
-        btree.insert(66u16, "い".to_string());
-        btree.insert(44u16, "あふれちゃう".to_string());
-        btree.insert(35u16, "add".to_string());    
-        btree.insert(58u16, "i am 58".to_string());
-        btree.insert(100, "こんどはどうだ".to_string());
-        btree.insert(16, "sixteen".to_string());
-    }
-
-    let mut btree = BTree::<u16, String>::create(p);
+        let btree = BTree::<u16, String>::create(p);
+        btree.insert(22u16, "abc".to_string()).unwrap();
+        btree.insert(55u16, "defg".to_string()).unwrap();
+        btree.insert(33u16, "あ".to_string()).unwrap();
+        btree.insert(66u16, "い".to_string()).unwrap();
+        btree.insert(44u16, "あふれちゃう".to_string()).unwrap();
+        btree.insert(35u16, "add".to_string()).unwrap();
+        btree.insert(58u16, "i am 58".to_string()).unwrap();
+        btree.insert(100, "こんどはどうだ".to_string()).unwrap();
+        btree.insert(16, "sixteen".to_string()).unwrap();
+    }
+
+    let btree = BTree::<u16, String>::create(p);
     println!("{:?}", btree);
     if btree.search(&18).is_err() {
-        btree.insert(18, "新成人".to_string());
+        btree.insert(18, "新成人".to_string()).unwrap();
         println!("{:?}", btree);
     }
 
@@ -177,32 +209,32 @@ fn test_split_nested() {
 fn test_split_branch() {
     let p = "sample/test_split_branch";
     if File::open(p).is_err() {
-        let mut btree = BTree::<u16, String>::create(p);
-        btree.insert(22, "abc".to_string());
-        btree.insert(55, "defg".to_string());
-        btree.insert(33, "あ".to_string());
-        btree.insert(66, "い".to_string());
-        btree.insert(44, "あふれちゃう".to_string());
-        btree.insert(35, "add".to_string());    
-        btree.insert(58, "i am 58".to_string());
-        btree.insert(100, "こんどはどうだ".to_string());
-        btree.insert(16, "sixteen".to_string());
-        btree.insert(18, "新成人".to_string());
-        btree.insert(99, "ナインティナ".to_string());
-        btree.insert(77, "lucky seven!!".to_string());
-        btree.insert(41, "いつまでやるんよ".to_string());
-        btree.insert(7, "七転".to_string());
-        btree.insert(8, "八倒".to_string());
-        btree.insert(25, "around thirty".to_string());
-        btree.insert(64, "8bit".to_string());
-        btree.insert(13, "金曜日".to_string());
-        btree.insert(50, "50:50".to_string());
-    }
-
-    let mut btree = BTree::<u16, String>::create(p);
+        let btree = BTree::<u16, String>::create(p);
+        btree.insert(22, "abc".to_string()).unwrap();
+        btree.insert(55, "defg".to_string()).unwrap();
+        btree.insert(33, "あ".to_string()).unwrap();
+        btree.insert(66, "い".to_string()).unwrap();
+        btree.insert(44, "あふれちゃう".to_string()).unwrap();
+        btree.insert(35, "add".to_string()).unwrap();
+        btree.insert(58, "i am 58".to_string()).unwrap();
+        btree.insert(100, "こんどはどうだ".to_string()).unwrap();
+        btree.insert(16, "sixteen".to_string()).unwrap();
+        btree.insert(18, "新成人".to_string()).unwrap();
+        btree.insert(99, "ナインティナ".to_string()).unwrap();
+        btree.insert(77, "lucky seven!!".to_string()).unwrap();
+        btree.insert(41, "いつまでやるんよ".to_string()).unwrap();
+        btree.insert(7, "七転".to_string()).unwrap();
+        btree.insert(8, "八倒".to_string()).unwrap();
+        btree.insert(25, "around thirty".to_string()).unwrap();
+        btree.insert(64, "8bit".to_string()).unwrap();
+        btree.insert(13, "金曜日".to_string()).unwrap();
+        btree.insert(50, "50:50".to_string()).unwrap();
+    }
+
+    let btree = BTree::<u16, String>::create(p);
     println!("{:?}", btree);
     if btree.search(&28).is_err() {
-        btree.insert(28, "I am perfect number.".to_string());
+        btree.insert(28, "I am perfect number.".to_string()).unwrap();
         println!("{:?}", btree);
     }
 
@@ -210,6 +242,268 @@ fn test_split_branch() {
     assert_eq!(btree.search(&28), Ok("I am perfect number.".to_string()));
 }
 
+#[test]
+fn test_update() {
+    let p = "test_update";
+    let btree = BTree::<u16, String>::create(p);
+    btree.insert(22, "abc".to_string()).unwrap();
+    btree.insert(55, "defg".to_string()).unwrap();
+    btree.insert(33, "あ".to_string()).unwrap();
+    assert_eq!(btree.update(&55, "hijk".to_string()), Ok(()));
+    assert_eq!(btree.update(&33, "あいう".to_string()), Ok(()));
+    assert_eq!(btree.update(&44, "none".to_string()), Err(Error::NotFound));
+
+    drop(btree);
+    let btree = BTree::<u16, String>::create(p);
+    let _ = remove_file(p);
+    assert_eq!(btree.search(&55), Ok("hijk".to_string()));
+    assert_eq!(btree.search(&33), Ok("あいう".to_string()));
+}
+
+#[test]
+fn test_delete() {
+    let p = "test_delete";
+    let btree = BTree::<u16, String>::create(p);
+    btree.insert(22, "abc".to_string()).unwrap();
+    btree.insert(55, "defg".to_string()).unwrap();
+    btree.delete(&22);
+
+    drop(btree);
+    let btree = BTree::<u16, String>::create(p);
+    let _ = remove_file(p);
+    assert_eq!(btree.search(&22), Err(Error::NotFound));
+    assert_eq!(btree.search(&55), Ok("defg".to_string()));
+}
+
+#[test]
+fn test_prefix_compression() {
+    let p = "test_prefix_compression";
+    let options = Options { prefix_compression: true, ..Default::default() };
+    let btree = BTree::<String, u16>::create_with_options(p, options).unwrap();
+    for (i, name) in ["user_alice", "user_bob", "user_carol", "user_dave", "user_eve"].iter().enumerate() {
+        btree.insert(name.to_string(), i as u16).unwrap();
+    }
+
+    drop(btree);
+    let btree = BTree::<String, u16>::create(p);
+    let _ = remove_file(p);
+    assert!(btree.options.prefix_compression);
+    assert_eq!(btree.search(&"user_alice".to_string()), Ok(0));
+    assert_eq!(btree.search(&"user_eve".to_string()), Ok(4));
+}
+
+#[test]
+fn test_separator() {
+    let lower = "aardvark".to_string();
+    let upper = "abacus".to_string();
+    assert_eq!(String::separator(&lower, &upper), "ab");
+    assert_eq!(String::separator(&"abc".to_string(), &"abcd".to_string()), "abcd");
+    assert_eq!(String::separator(&"あい".to_string(), &"あう".to_string()), "あう");
+    assert_eq!(u16::separator(&3, &7), 7);
+}
+
+#[test]
+fn test_split_separator() {
+    let p = "test_split_separator";
+    let btree = BTree::<String, u16>::create(p);
+    btree.insert("aardvark".to_string(), 1).unwrap();
+    btree.insert("abacus".to_string(), 2).unwrap();
+    btree.insert("abalone".to_string(), 3).unwrap();
+    btree.insert("abbey".to_string(), 4).unwrap();
+
+    let branch = match btree.read_node(btree.root_page_id().unwrap()).unwrap() {
+        Node::Branch(branch) => branch,
+        Node::Leaf(_) => panic!("root should have been split"),
+    };
+    let _ = remove_file(p);
+    assert_eq!(branch.slotted.keys(), ["ab"]);
+    assert_eq!(btree.search(&"aardvark".to_string()), Ok(1));
+    assert_eq!(btree.search(&"abacus".to_string()), Ok(2));
+}
+
+#[test]
+fn test_split_many() {
+    let p = "test_split_many";
+    let btree = BTree::<u16, String>::create(p);
+    for i in 0..500u16 {
+        let key = (i * 37) % 500;
+        btree.insert(key, format!("v{}", key)).unwrap();
+    }
+
+    let not_found = (0..500u16)
+        .filter(|key| btree.search(key) != Ok(format!("v{}", key)))
+        .collect::<Vec<_>>();
+    let _ = remove_file(p);
+    assert_eq!(not_found, []);
+}
+
+#[test]
+fn test_split_fits_page() {
+    let p = "test_split_fits_page";
+    let btree = BTree::<String, Vec<u8>>::create(p);
+    btree.insert("user_2245".to_string(), vec![0; 4]).unwrap();
+    btree.insert("user_28288".to_string(), vec![0; 6]).unwrap();
+    btree.insert("user_50".to_string(), vec![0; 2]).unwrap();
+    btree.insert("user_23238".to_string(), vec![0; 4]).unwrap();
+
+    let problems = btree.check();
+    let keys = btree.range(..).into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+    let _ = remove_file(p);
+    assert_eq!(problems, vec![]);
+    assert_eq!(keys, ["user_2245", "user_23238", "user_28288", "user_50"]);
+}
+
+#[test]
+fn test_too_large() {
+    let p = "test_too_large";
+    let btree = BTree::<String, Vec<u8>>::create(p);
+    let into_empty = btree.insert("a".to_string(), vec![0; 70]);
+    let empty_pages = btree.storage.next_page_id();
+    for i in 0..10u8 {
+        btree.insert(format!("k{}", i), vec![i; 4]).unwrap();
+    }
+    let pages = btree.storage.next_page_id();
+    let into_full = btree.insert("k5".to_string(), vec![0; 70]);
+    let long_key = btree.insert("k".repeat(30), vec![]);
+    let updated = btree.update(&"k5".to_string(), vec![0; 70]);
+
+    let problems = btree.check();
+    let found = btree.search(&"k5".to_string());
+    let next_page_id = btree.storage.next_page_id();
+    let _ = remove_file(p);
+    assert_eq!(into_empty, Err(Error::TooLarge));
+    assert_eq!(empty_pages, 1);
+    assert_eq!((into_full, long_key, updated), (Err(Error::TooLarge), Err(Error::TooLarge), Err(Error::TooLarge)));
+    assert_eq!(next_page_id, pages);
+    assert_eq!(problems, vec![]);
+    assert_eq!(found, Ok(vec![5; 4]));
+}
+
+#[test]
+fn test_random_inserts_deletes() {
+    let p = "test_random_inserts_deletes";
+    let btree = BTree::<String, Vec<u8>>::create(p);
+    let mut expected = BTreeMap::new();
+    // xorshift, so a failure replays the same operations
+    let mut state = 0x2545_f491u32;
+    let mut random = move |bound: u32| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state % bound
+    };
+    for _ in 0..2000 {
+        let digits = random(16) + 1;
+        let key = format!("user_{}", random(1 << digits));
+        if random(4) == 0 {
+            btree.delete(&key);
+            expected.remove(&key);
+        } else {
+            let value = vec![random(256) as u8; random(8) as usize];
+            if expected.insert(key.clone(), value.clone()).is_some() {
+                btree.update(&key, value).unwrap();
+            } else {
+                btree.insert(key, value).unwrap();
+            }
+        }
+    }
+
+    let problems = btree.check();
+    let entries = btree.range(..);
+    let _ = remove_file(p);
+    assert_eq!(problems, vec![]);
+    assert_eq!(entries, expected.into_iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_varint_pointers() {
+    let p = "test_varint_pointers";
+    let options = Options { pointer_format: PointerFormat::Varint, ..Default::default() };
+    let btree = BTree::<u16, String>::create_with_options(p, options).unwrap();
+    for i in 0..200u16 {
+        let key = (i * 37) % 200;
+        btree.insert(key, format!("v{}", key)).unwrap();
+    }
+
+    drop(btree);
+    let btree = BTree::<u16, String>::create(p);
+    let root_format = match btree.read_node(btree.root_page_id().unwrap()).unwrap() {
+        Node::Branch(branch) => branch.slotted.pointer_format(),
+        Node::Leaf(leaf) => leaf.slotted.pointer_format(),
+    };
+    let not_found = (0..200u16)
+        .filter(|key| btree.search(key) != Ok(format!("v{}", key)))
+        .collect::<Vec<_>>();
+    let _ = remove_file(p);
+    assert_eq!(btree.options.pointer_format, PointerFormat::Varint);
+    assert_eq!(root_format, PointerFormat::Varint);
+    assert_eq!(not_found, []);
+}
+
+#[test]
+fn test_reverse_comparator() {
+    let p = "test_reverse_comparator";
+    let btree = BTree::<u16, String>::create_with_comparator(p, Options::default(), Comparator::reverse()).unwrap();
+    for key in 0..40u16 {
+        btree.insert(key, format!("v{}", key)).unwrap();
+    }
+
+    drop(btree);
+    let btree = BTree::<u16, String>::create_with_comparator(p, Options::default(), Comparator::reverse()).unwrap();
+    let leaf_keys = match btree.read_node(btree.root_page_id().unwrap()).unwrap() {
+        Node::Branch(branch) => branch.slotted.keys(),
+        Node::Leaf(_) => panic!("root should have been split"),
+    };
+    let _ = remove_file(p);
+    assert!(leaf_keys.windows(2).all(|keys| keys[0] > keys[1]));
+    assert_eq!(btree.search(&0), Ok("v0".to_string()));
+    assert_eq!(btree.search(&39), Ok("v39".to_string()));
+}
+
+#[test]
+fn test_case_insensitive_comparator() {
+    let p = "test_case_insensitive_comparator";
+    let btree = BTree::<String, u16>::create_with_comparator(p, Options::default(), Comparator::case_insensitive()).unwrap();
+    for (i, name) in ["Alice", "bob", "CAROL", "dave", "Eve", "frank"].iter().enumerate() {
+        btree.insert(name.to_string(), i as u16).unwrap();
+    }
+    let _ = remove_file(p);
+    assert_eq!(btree.search(&"ALICE".to_string()), Ok(0));
+    assert_eq!(btree.search(&"carol".to_string()), Ok(2));
+    assert_eq!(btree.search(&"FRANK".to_string()), Ok(5));
+}
+
+#[test]
+fn test_string_comparators() {
+    let numeric = Comparator::numeric();
+    let mut files = vec!["file10", "file2", "file1", "file02b"].into_iter().map(String::from).collect::<Vec<_>>();
+    files.sort_by(numeric.compare);
+    assert_eq!(files, ["file1", "file2", "file02b", "file10"]);
+    assert_eq!((numeric.separator)(&"file9".to_string(), &"file10".to_string()), "file10");
+
+    let dictionary = Comparator::dictionary();
+    let mut words = vec!["cooperate", "coop", "co-op", "Coop"].into_iter().map(String::from).collect::<Vec<_>>();
+    words.sort_by(dictionary.compare);
+    assert_eq!(words, ["Coop", "co-op", "coop", "cooperate"]);
+
+    let case_insensitive = Comparator::case_insensitive();
+    assert_eq!((case_insensitive.separator)(&"Apple".to_string(), &"apricot".to_string()), "apr");
+}
+
+#[test]
+fn test_comparator_mismatch() {
+    let p = "test_comparator_mismatch";
+    let btree = BTree::<u16, String>::create_with_comparator(p, Options::default(), Comparator::reverse()).unwrap();
+    btree.insert(1, "one".to_string()).unwrap();
+    drop(btree);
+
+    let result = BTree::<u16, String>::create_with_comparator(p, Options::default(), Comparator::natural());
+    let panicked = std::panic::catch_unwind(|| BTree::<u16, String>::create(p)).is_err();
+    let _ = remove_file(p);
+    assert_eq!(result.err(), Some(Error::ComparatorMismatch("reverse".to_string())));
+    assert!(panicked);
+}
+
 // #[allow(dead_code)]
 // fn file_bytes(path: impl AsRef<Path>) -> Vec<u8> {
 //     let mut f = OpenOptions::new()
@@ -219,3 +513,916 @@ fn test_split_branch() {
 //     let _ = f.read_to_end(&mut buf);
 //     buf
 // }
+
+#[test]
+fn test_concurrent_access() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<BTree<u16, String>>();
+
+    let p = "test_concurrent_access";
+    let btree = Arc::new(BTree::<u16, String>::create(p));
+    for key in 0..100u16 {
+        btree.insert(key, format!("v{}", key)).unwrap();
+    }
+
+    let writers = (0..2u16).map(|w| {
+        let btree = Arc::clone(&btree);
+        thread::spawn(move || {
+            for key in (100 + w * 50)..(150 + w * 50) {
+                btree.insert(key, format!("v{}", key)).unwrap();
+            }
+        })
+    });
+    let readers = (0..4).map(|_| {
+        let btree = Arc::clone(&btree);
+        thread::spawn(move || {
+            (0..100u16).all(|key| btree.search(&key) == Ok(format!("v{}", key)))
+        })
+    });
+    let writers = writers.collect::<Vec<_>>();
+    let readers = readers.collect::<Vec<_>>();
+    writers.into_iter().for_each(|writer| writer.join().unwrap());
+    let all_found = readers.into_iter().all(|reader| reader.join().unwrap());
+
+    let not_found = (0..200u16)
+        .filter(|key| btree.search(key) != Ok(format!("v{}", key)))
+        .collect::<Vec<_>>();
+    let _ = remove_file(p);
+    assert!(all_found);
+    assert_eq!(not_found, []);
+}
+
+#[test]
+fn test_concurrent_writers() {
+    let p = "test_concurrent_writers";
+    let btree = Arc::new(BTree::<u16, String>::create(p));
+    let writers = (0..4u16).map(|w| {
+        let btree = Arc::clone(&btree);
+        thread::spawn(move || {
+            for i in 0..100u16 {
+                let key = ((i * 37) % 100) * 4 + w;
+                btree.insert(key, format!("v{}", key)).unwrap();
+                if key % 3 == 0 {
+                    btree.delete(&key);
+                }
+            }
+        })
+    });
+    writers.collect::<Vec<_>>().into_iter().for_each(|writer| writer.join().unwrap());
+    drop(btree);
+
+    let btree = BTree::<u16, String>::create(p);
+    let not_found = (0..400u16)
+        .filter(|key| key % 3 != 0)
+        .filter(|key| btree.search(key) != Ok(format!("v{}", key)))
+        .collect::<Vec<_>>();
+    let deleted = (0..400u16)
+        .filter(|key| key % 3 == 0)
+        .filter(|key| btree.search(key).is_ok())
+        .collect::<Vec<_>>();
+    let _ = remove_file(p);
+    assert_eq!(not_found, []);
+    assert_eq!(deleted, []);
+}
+
+#[test]
+fn test_snapshot() {
+    let p = "test_snapshot";
+    let btree = BTree::<u16, String>::create(p);
+    for key in 0..40u16 {
+        btree.insert(key, format!("v{}", key)).unwrap();
+    }
+
+    let snapshot = btree.snapshot();
+    for key in 40..80u16 {
+        btree.insert(key, format!("v{}", key)).unwrap();
+    }
+    btree.delete(&3);
+    let _ = btree.update(&5, "updated".to_string());
+
+    let old = (snapshot.search(&3), snapshot.search(&5), snapshot.search(&50));
+    let new = (btree.search(&3), btree.search(&5), btree.search(&50));
+    let old_range = snapshot.range(2..6);
+    let new_range = btree.range(2..6);
+    let old_len = snapshot.range(..).len();
+    drop(snapshot);
+    let image_count = btree.storage.image_count();
+    let _ = remove_file(p);
+
+    assert_eq!(old, (Ok("v3".to_string()), Ok("v5".to_string()), Err(Error::NotFound)));
+    assert_eq!(new, (Err(Error::NotFound), Ok("updated".to_string()), Ok("v50".to_string())));
+    assert_eq!(old_range, [(2, "v2".to_string()), (3, "v3".to_string()), (4, "v4".to_string()), (5, "v5".to_string())]);
+    assert_eq!(new_range, [(2, "v2".to_string()), (4, "v4".to_string()), (5, "updated".to_string())]);
+    assert_eq!(old_len, 40);
+    assert_eq!(image_count, 0);
+}
+
+#[test]
+fn test_range() {
+    let p = "test_range";
+    let btree = BTree::<u16, String>::create_with_comparator(p, Options::default(), Comparator::reverse()).unwrap();
+    for i in 0..200u16 {
+        let key = (i * 37) % 200;
+        btree.insert(key, format!("v{}", key)).unwrap();
+    }
+
+    let all = btree.range(..).into_iter().map(|(k, _)| k).collect::<Vec<_>>();
+    let some = btree.range((Bound::Included(120), Bound::Included(80))).into_iter().map(|(k, _)| k).collect::<Vec<_>>();
+    let _ = remove_file(p);
+    assert_eq!(all, (0..200u16).rev().collect::<Vec<_>>());
+    assert_eq!(some, (80..=120u16).rev().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_pages() {
+    let p = "test_pages";
+    let btree = BTree::<u16, u16>::create(p);
+    let empty = btree.pages();
+    for i in 0..40u16 {
+        btree.insert(i, i).unwrap();
+    }
+
+    let pages = btree.pages();
+    let _ = remove_file(p);
+    assert!(empty.is_empty());
+    assert_eq!(pages[0].page_id, btree.root_page_id().unwrap());
+    assert!(pages[0].branch);
+    let leaves = pages.iter().filter(|page| !page.branch).collect::<Vec<_>>();
+    let height = leaves[0].depth;
+    assert!(leaves.iter().all(|page| page.depth == height));
+    assert_eq!(leaves.iter().map(|page| page.keys).sum::<usize>(), 40);
+}
+
+#[test]
+fn test_check() {
+    let p = "test_check";
+    let btree = BTree::<u16, u16>::create(p);
+    for i in 0..40u16 {
+        btree.insert(i, i).unwrap();
+    }
+    let sound = btree.check();
+
+    let mut leaked = btree.storage.allocate_page().unwrap();
+    btree.storage.write_page(&mut leaked).unwrap();
+    let leaf_page_id = btree.pages().iter().find(|page| !page.branch).unwrap().page_id;
+    let mut leaf = Page::new(leaf_page_id);
+    btree.storage.read_page(&mut leaf).unwrap();
+    leaf.set_u16_bytes(2, u16::MAX);
+    btree.storage.write_page(&mut leaf).unwrap();
+    let problems = btree.check();
+    let _ = remove_file(p);
+    assert_eq!(sound, vec![]);
+    assert_eq!(problems, vec![
+        Problem::Damaged { page_id: leaf_page_id, damage: PageDamage::FreeSpaceOutOfPage },
+        Problem::Leaked { page_id: leaked.id },
+    ]);
+}
+
+#[test]
+fn test_check_undecodable() {
+    let p = "test_check_undecodable";
+    let btree = BTree::<String, u16>::create(p);
+    for i in 0..40u16 {
+        btree.insert(format!("key{:02}", i), i).unwrap();
+    }
+    let leaf_page_id = btree.pages().iter().find(|page| !page.branch).unwrap().page_id;
+    let mut leaf = Page::new(leaf_page_id);
+    btree.storage.read_page(&mut leaf).unwrap();
+    // the last key byte of the slot at the end of the page, right before its u16 value
+    leaf.bytes[PAGE_SIZE - 3] = 0xFF;
+    btree.storage.write_page(&mut leaf).unwrap();
+    let problems = btree.check();
+    let _ = remove_file(p);
+    assert!(matches!(problems[..], [Problem::Damaged { page_id, damage: PageDamage::Undecodable(_) }] if page_id == leaf_page_id), "{:?}", problems);
+}
+
+#[test]
+fn test_to_dot() {
+    let p = "test_to_dot";
+    let btree = BTree::<u16, u16>::create(p);
+    for i in 0..40u16 {
+        btree.insert(i, i).unwrap();
+    }
+
+    let dot = btree.to_dot();
+    let with_siblings = btree.to_dot_with(true);
+    let pages = btree.pages();
+    let _ = remove_file(p);
+    let leaves = pages.iter().filter(|page| !page.branch).count();
+    assert!(dot.starts_with("digraph btree {"));
+    assert_eq!(dot.matches("[label=").count(), pages.len());
+    assert_eq!(dot.matches("->").count(), pages.len() - 1);
+    assert_eq!(with_siblings.matches("style=dashed").count(), leaves - 1);
+}
+
+#[test]
+fn test_stats() {
+    let p = "test_stats";
+    let btree = BTree::<u16, u16>::create(p);
+    let empty = btree.stats();
+    for i in 0..40u16 {
+        btree.insert(i, i).unwrap();
+    }
+    for i in 0..10u16 {
+        btree.delete(&(i * 4));
+    }
+
+    let stats = btree.stats();
+    let pages = btree.pages();
+    let _ = remove_file(p);
+    assert_eq!(empty, Stats::default());
+    assert_eq!(stats.keys, 30);
+    assert_eq!(stats.height, pages.iter().map(|page| page.depth).max().unwrap() + 1);
+    assert_eq!(stats.branch_pages + stats.leaf_pages, pages.len());
+    assert!(stats.min_fill <= stats.average_fill && stats.average_fill <= stats.max_fill && stats.max_fill <= 1.0);
+    assert!(stats.fragmented_bytes > 0);
+}
+
+#[test]
+fn test_export_import() {
+    let p = "test_export_import";
+    let q = "test_export_import_copy";
+    let btree = BTree::<String, String>::create(p);
+    let values = ["plain", "comma, \"quoted\"", "line\r\nbreak", "tab\tand \\ ぽ", ""];
+    for (i, value) in values.iter().enumerate() {
+        btree.insert(format!("k{}", i), value.to_string()).unwrap();
+    }
+
+    let mut jsonl = vec![];
+    let mut csv = vec![];
+    let exported = btree.export(&mut jsonl, ExportFormat::JsonLines).unwrap();
+    let _ = btree.export(&mut csv, ExportFormat::Csv);
+    let copy = BTree::<String, String>::create(q);
+    copy.insert("k0".to_string(), "stale".to_string()).unwrap();
+    let imported = copy.import(&jsonl[..], ExportFormat::JsonLines);
+    let from_jsonl = copy.range(..);
+    let _ = remove_file(q);
+    let copy = BTree::<String, String>::create(q);
+    let _ = copy.import(&csv[..], ExportFormat::Csv);
+    let from_csv = copy.range(..);
+    let broken = copy.import(&b"k9,v9\nk10\n"[..], ExportFormat::Csv);
+    let _ = remove_file(p);
+    let _ = remove_file(q);
+    assert_eq!(exported, values.len());
+    assert_eq!(imported, Ok(values.len()));
+    assert_eq!(from_jsonl, btree.range(..));
+    assert_eq!(from_csv, btree.range(..));
+    assert_eq!(broken, Err(Error::Parse(2, "expected 2 fields but found 1".to_string())));
+}
+
+#[test]
+fn test_import_merges() {
+    let p = "test_import_merges";
+    let btree = BTree::<u16, u16>::create(p);
+    for key in (0..300u16).step_by(2) {
+        btree.insert(key, 0).unwrap();
+    }
+    // out of order, and key 7 twice
+    let mut csv = String::new();
+    for key in (100..400u16).rev() {
+        csv.push_str(&format!("{},{}\r\n", key, key));
+    }
+    csv.push_str("7,1\r\n7,2\r\n");
+    let imported = btree.import(csv.as_bytes(), ExportFormat::Csv);
+    let slots = btree.range(..);
+    let stats = btree.stats();
+    let problems = btree.check();
+    drop(btree);
+    let reopened = BTree::<u16, u16>::create(p).range(..);
+    let _ = remove_file(p);
+    let mut expected = (0..100u16).step_by(2).map(|key| (key, 0)).collect::<BTreeMap<_, _>>();
+    expected.extend((100..400u16).map(|key| (key, key)));
+    expected.insert(7, 2);
+    let expected = expected.into_iter().collect::<Vec<_>>();
+    assert_eq!(imported, Ok(302));
+    assert_eq!(slots, expected);
+    assert_eq!(reopened, expected);
+    assert_eq!(problems, []);
+    // full leaves, as a bulk load leaves them
+    assert!(stats.average_fill > 0.8, "{}", stats.average_fill);
+}
+
+#[test]
+fn test_memory_store() {
+    let btree = BTree::<u16, String>::create_with_store(MemoryStore::new(), Options::default(), Comparator::natural()).unwrap();
+    for i in 0..200u16 {
+        btree.insert(i, format!("v{}", i)).unwrap();
+    }
+    let snapshot = btree.snapshot();
+    for i in (0..200u16).step_by(2) {
+        btree.delete(&i);
+    }
+
+    assert_eq!(snapshot.range(..).len(), 200);
+    assert_eq!(btree.range(..).len(), 100);
+    assert_eq!(btree.search(&101), Ok("v101".to_string()));
+    assert_eq!(btree.check(), vec![]);
+}
+
+#[cfg(all(any(target_os = "linux", target_os = "macos"), target_pointer_width = "64"))]
+#[test]
+fn test_mmap_store() {
+    let p = "test_mmap_store";
+    let btree = BTree::<u16, String>::create_with_store(MmapStore::open(p).unwrap(), Options::default(), Comparator::natural()).unwrap();
+    for i in 0..2000u16 {
+        btree.insert(i, format!("v{}", i)).unwrap();
+    }
+    let next_page_id = btree.storage.next_page_id();
+    drop(btree);
+
+    let file_len = std::fs::metadata(p).unwrap().len();
+    let from_file = BTree::<u16, String>::create(p);
+    let slots = from_file.range(..);
+    let problems = from_file.check();
+    drop(from_file);
+
+    // a view points into the mapping, the next one of the page at the same bytes
+    let store = MmapStore::open(p).unwrap();
+    let view = store.page(1).unwrap();
+    let (viewed, at) = (*view, view.as_ptr());
+    drop(view);
+    let same_bytes = store.page(1).unwrap().as_ptr() == at;
+    let mut read = [0; PAGE_SIZE];
+    store.read_page(1, &mut read).unwrap();
+    let past_end = store.page(next_page_id).err().map(|error| error.kind());
+    drop(store);
+    let _ = remove_file(p);
+    assert_eq!(file_len, (next_page_id as usize * PAGE_SIZE) as u64);
+    assert_eq!(slots, (0..2000u16).map(|i| (i, format!("v{}", i))).collect::<Vec<_>>());
+    assert_eq!(problems, vec![]);
+    assert!(same_bytes);
+    assert_eq!(viewed, read);
+    assert_eq!(past_end, Some(std::io::ErrorKind::UnexpectedEof));
+}
+
+#[test]
+fn test_flush() {
+    let p = "test_flush";
+    let options = Options { durability: Durability::OnFlush, ..Default::default() };
+    let on_disk_root = || {
+        // nothing reaches the file before the first flush
+        let bytes = std::fs::read(p).unwrap();
+        bytes.get(..2).map_or(0, |root| u16::from_le_bytes([root[0], root[1]]))
+    };
+    let btree = BTree::<u16, u16>::create_with_options(p, options).unwrap();
+    for i in 0..50u16 {
+        btree.insert(i, i).unwrap();
+    }
+    let before_flush = on_disk_root();
+    let flushed = btree.flush();
+    let after_flush = on_disk_root();
+    let root_page_id = btree.root_page_id();
+    for i in 50..100u16 {
+        btree.insert(i, i).unwrap();
+    }
+    drop(btree);
+
+    let btree = BTree::<u16, u16>::create(p);
+    let slots = btree.range(..);
+    let _ = remove_file(p);
+    assert_eq!(before_flush, 0);
+    assert_eq!(flushed, Ok(()));
+    assert_eq!(Some(after_flush), root_page_id);
+    assert_eq!(slots.len(), 100);
+}
+
+// drops every write past the budget, as if the process died there
+struct Crashing {
+    memory: Arc<MemoryStore>,
+    budget: Arc<AtomicUsize>,
+}
+
+impl Crashing {
+    fn spend(&self) -> bool {
+        self.budget.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |budget| budget.checked_sub(1)).is_ok()
+    }
+}
+
+impl PageStore for Crashing {
+    fn read_page(&self, page_id: u16, bytes: &mut [u8; PAGE_SIZE]) -> io::Result<()> {
+        self.memory.read_page(page_id, bytes)
+    }
+
+    fn write_page(&self, page_id: u16, bytes: &[u8; PAGE_SIZE]) -> io::Result<()> {
+        if self.spend() { self.memory.write_page(page_id, bytes) } else { Ok(()) }
+    }
+
+    fn allocate(&self) -> u16 {
+        self.memory.allocate()
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn truncate(&self, len: u16) -> io::Result<()> {
+        if self.spend() { self.memory.truncate(len) } else { Ok(()) }
+    }
+
+    fn len(&self) -> u16 {
+        self.memory.len()
+    }
+}
+
+#[test]
+fn test_flush_crash() {
+    let options = Options { durability: Durability::OnFlush, ..Default::default() };
+    for crash_after in 0..60 {
+        let memory = Arc::new(MemoryStore::new());
+        let budget = Arc::new(AtomicUsize::new(usize::MAX));
+        let store = Crashing { memory: Arc::clone(&memory), budget: Arc::clone(&budget) };
+        let btree = BTree::<u16, u16>::create_with_store(store, options, Comparator::natural()).unwrap();
+        for i in 0..20u16 {
+            btree.insert(i, i).unwrap();
+        }
+        btree.flush().unwrap();
+        budget.store(crash_after, Ordering::SeqCst);
+        // splits the root and commits again
+        for i in 20..60u16 {
+            btree.insert(i, i).unwrap();
+        }
+        let _ = btree.flush();
+        budget.store(0, Ordering::SeqCst);
+        drop(btree);
+
+        let store = Crashing { memory, budget: Arc::new(AtomicUsize::new(usize::MAX)) };
+        let btree = BTree::<u16, u16>::create_with_store(store, options, Comparator::natural()).unwrap();
+        let keys = btree.range(..).into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+        assert!(keys == (0..20).collect::<Vec<_>>() || keys == (0..60).collect::<Vec<_>>(), "{}: {:?}", crash_after, keys);
+        assert_eq!(btree.check(), vec![], "{}", crash_after);
+    }
+}
+
+// reads a crashed file but fails every write, like a full disk
+struct Unwritable(Arc<MemoryStore>);
+
+impl PageStore for Unwritable {
+    fn read_page(&self, page_id: u16, bytes: &mut [u8; PAGE_SIZE]) -> io::Result<()> {
+        self.0.read_page(page_id, bytes)
+    }
+
+    fn write_page(&self, _: u16, _: &[u8; PAGE_SIZE]) -> io::Result<()> {
+        Err(io::Error::other("no space left"))
+    }
+
+    fn allocate(&self) -> u16 {
+        self.0.allocate()
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn truncate(&self, _: u16) -> io::Result<()> {
+        Err(io::Error::other("no space left"))
+    }
+
+    fn len(&self) -> u16 {
+        self.0.len()
+    }
+}
+
+#[test]
+fn test_recover_failed() {
+    let options = Options { durability: Durability::OnFlush, ..Default::default() };
+    let mut failed = 0;
+    for crash_after in 0..60 {
+        let memory = Arc::new(MemoryStore::new());
+        let budget = Arc::new(AtomicUsize::new(usize::MAX));
+        let store = Crashing { memory: Arc::clone(&memory), budget: Arc::clone(&budget) };
+        let btree = BTree::<u16, u16>::create_with_store(store, options, Comparator::natural()).unwrap();
+        for i in 0..20u16 {
+            btree.insert(i, i).unwrap();
+        }
+        btree.flush().unwrap();
+        budget.store(crash_after, Ordering::SeqCst);
+        for i in 20..60u16 {
+            btree.insert(i, i).unwrap();
+        }
+        let _ = btree.flush();
+        budget.store(0, Ordering::SeqCst);
+        drop(btree);
+
+        // a journal that cannot be replayed fails the open instead of showing the torn tree
+        match BTree::<u16, u16>::create_with_store(Unwritable(memory), options, Comparator::natural()) {
+            Ok(btree) => assert_eq!(btree.check(), vec![], "{}", crash_after),
+            Err(error) => {
+                assert!(matches!(error, Error::Io(_)), "{}: {:?}", crash_after, error);
+                failed += 1;
+            }
+        }
+    }
+    assert!(failed > 0);
+}
+
+// fails every read of one page, as a bad sector would
+struct FailingReads {
+    memory: Arc<MemoryStore>,
+    page_id: u16,
+}
+
+impl PageStore for FailingReads {
+    fn read_page(&self, page_id: u16, bytes: &mut [u8; PAGE_SIZE]) -> io::Result<()> {
+        if page_id == self.page_id {
+            return Err(io::Error::other("bad sector"));
+        }
+        self.memory.read_page(page_id, bytes)
+    }
+
+    fn write_page(&self, page_id: u16, bytes: &[u8; PAGE_SIZE]) -> io::Result<()> {
+        self.memory.write_page(page_id, bytes)
+    }
+
+    fn allocate(&self) -> u16 {
+        self.memory.allocate()
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn truncate(&self, len: u16) -> io::Result<()> {
+        self.memory.truncate(len)
+    }
+
+    fn len(&self) -> u16 {
+        self.memory.len()
+    }
+}
+
+#[test]
+fn test_read_failed() {
+    let memory = Arc::new(MemoryStore::new());
+    let store = FailingReads { memory: Arc::clone(&memory), page_id: u16::MAX };
+    let btree = BTree::<u16, u16>::create_with_store(store, Options::default(), Comparator::natural()).unwrap();
+    for i in 0..10u16 {
+        btree.insert(i, i).unwrap();
+    }
+    let root_page_id = btree.root_page_id().unwrap();
+    drop(btree);
+
+    let store = FailingReads { memory, page_id: root_page_id };
+    let btree = BTree::<u16, u16>::create_with_store(store, Options::default(), Comparator::natural()).unwrap();
+    let found = btree.search(&3);
+    let inserted = btree.insert(10, 10);
+    let problems = btree.check();
+    assert_eq!(found, Err(Error::Io("bad sector".to_string())));
+    assert_eq!(inserted, Err(Error::Io("bad sector".to_string())));
+    assert!(matches!(problems[..], [Problem::Unreadable { page_id, .. }, ..] if page_id == root_page_id), "{:?}", problems);
+}
+
+#[test]
+fn test_locked() {
+    let p = "test_locked";
+    let btree = BTree::<u16, u16>::create(p);
+    btree.insert(1, 1).unwrap();
+    let second = BTree::<u16, u16>::create_with_comparator(p, Options::default(), Comparator::natural()).err();
+    let with_options = BTree::<u16, u16>::create_with_options(p, Options::default()).err();
+    let read_only = FileStore::open_read_only(p).err();
+    drop(btree);
+
+    let readers = (FileStore::open_read_only(p), FileStore::open_read_only(p));
+    let writer = FileStore::open(p).err();
+    drop(readers);
+    let reopened = BTree::<u16, u16>::create(p).search(&1);
+    let _ = remove_file(p);
+    assert_eq!(second, Some(Error::Locked));
+    assert_eq!(with_options, Some(Error::Locked));
+    assert_eq!(read_only, Some(Error::Locked));
+    assert_eq!(writer, Some(Error::Locked));
+    assert_eq!(reopened, Ok(1));
+}
+
+#[test]
+fn test_open_read_only() {
+    let p = "test_open_read_only";
+    let btree = BTree::<u16, String>::create_with_comparator(p, Options::default(), Comparator::reverse()).unwrap();
+    for key in 0..50u16 {
+        btree.insert(key, format!("v{}", key)).unwrap();
+    }
+    drop(btree);
+
+    let mut permissions = std::fs::metadata(p).unwrap().permissions();
+    permissions.set_readonly(true);
+    std::fs::set_permissions(p, permissions).unwrap();
+    let read_only = BTree::<u16, String>::open_read_only_with(p, Comparator::reverse()).unwrap();
+    let other = BTree::<u16, String>::open_read_only_with(p, Comparator::reverse()).map(|_| ());
+    let writer = FileStore::open(p).err();
+    let mismatch = BTree::<u16, String>::open_read_only(p).err();
+    let found = read_only.search(&17);
+    let slots = read_only.range(..);
+    let problems = read_only.check();
+    drop(read_only);
+    let _ = remove_file(p);
+
+    let missing = BTree::<u16, String>::open_read_only("test_open_read_only_missing").err();
+    let created = std::path::Path::new("test_open_read_only_missing").exists();
+    assert_eq!(found, Ok("v17".to_string()));
+    assert_eq!(slots.len(), 50);
+    assert_eq!(slots[0].0, 49);
+    assert_eq!(problems, []);
+    assert_eq!(other, Ok(()));
+    assert_eq!(writer, Some(Error::Locked));
+    assert_eq!(mismatch, Some(Error::ComparatorMismatch("reverse".to_string())));
+    assert!(matches!(missing, Some(Error::Io(_))));
+    assert!(!created);
+}
+
+#[test]
+fn test_backup() {
+    let p = "test_backup";
+    let backup = "test_backup_copy";
+    let btree = Arc::new(BTree::<u16, String>::create(p));
+    for key in 0..300u16 {
+        btree.insert(key, format!("v{}", key)).unwrap();
+    }
+    for key in (0..300u16).step_by(3) {
+        btree.delete(&key);
+    }
+    let expected = btree.range(..);
+    let writer = {
+        let btree = Arc::clone(&btree);
+        thread::spawn(move || {
+            for key in 300..600u16 {
+                btree.insert(key, format!("v{}", key)).unwrap();
+            }
+        })
+    };
+    let copied = btree.backup_to(backup);
+    writer.join().unwrap();
+    let again = btree.backup_to(backup).err();
+
+    let copy = BTree::<u16, String>::open_read_only(backup).unwrap();
+    let slots = copy.range(..);
+    let problems = copy.check();
+    let pages = copy.pages().len();
+    drop(copy);
+    let _ = remove_file(p);
+    let _ = remove_file(backup);
+    assert_eq!(copied, Ok(pages));
+    assert!(matches!(again, Some(Error::Io(_))));
+    assert_eq!(problems, []);
+    // all of the keys the writer had inserted when the backup began and none after
+    let inserted = (300..300 + (slots.len() - expected.len()) as u16).map(|key| (key, format!("v{}", key)));
+    assert_eq!(slots, expected.into_iter().chain(inserted).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_vacuum() {
+    let p = "test_vacuum";
+    let btree = BTree::<u16, String>::create(p);
+    for key in 0..500u16 {
+        btree.insert(key, format!("v{}", key)).unwrap();
+    }
+    for key in 0..500u16 {
+        if key % 10 != 0 {
+            btree.delete(&key);
+        }
+    }
+    let before = std::fs::metadata(p).unwrap().len();
+    let snapshot = btree.snapshot();
+    let cut = btree.vacuum();
+    let snapshot_slots = snapshot.range(..);
+    drop(snapshot);
+    let after = std::fs::metadata(p).unwrap().len();
+    let problems = btree.check();
+    let slots = btree.range(..);
+    let free_pages = btree.stats().free_pages;
+
+    btree.vacuum_with_fill(0.5).unwrap();
+    let leaf_fills = btree.pages().iter().filter(|page| !page.branch).map(|page| page.fill()).collect::<Vec<_>>();
+    let half_problems = btree.check();
+    drop(btree);
+    let reopened = BTree::<u16, String>::create(p).range(..);
+    let _ = remove_file(p);
+    let expected = (0..500u16).step_by(10).map(|key| (key, format!("v{}", key))).collect::<Vec<_>>();
+    assert_eq!(cut.map(|cut| cut as u64 * PAGE_SIZE as u64), Ok(before - after));
+    assert!(after * 4 < before);
+    assert_eq!(snapshot_slots, expected);
+    assert_eq!(slots, expected);
+    assert_eq!(problems, []);
+    assert_eq!(free_pages, 0);
+    assert!(leaf_fills.len() > 1 && leaf_fills.iter().all(|fill| *fill <= 0.5));
+    assert_eq!(half_problems, []);
+    assert_eq!(reopened, expected);
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn test_compressed_store() {
+    let p = "test_compressed_store";
+    let plain = "test_compressed_store_plain";
+    let compressed = BTree::<u16, String>::create_with_store(CompressedStore::open(p, Lz).unwrap(), Options::default(), Comparator::natural()).unwrap();
+    let uncompressed = BTree::<u16, String>::create(plain);
+    for key in 0..300u16 {
+        compressed.insert(key, format!("{:-^20}", key)).unwrap();
+        uncompressed.insert(key, format!("{:-^20}", key)).unwrap();
+    }
+    // enough rewrites of the same pages to get the log rewritten
+    for round in 0..20u16 {
+        for key in 0..300u16 {
+            let _ = compressed.update(&key, format!("{:-^20}", key + round));
+        }
+    }
+    for key in 0..300u16 {
+        compressed.delete(&key);
+        uncompressed.delete(&key);
+        if key % 3 != 0 {
+            continue;
+        }
+        compressed.insert(key, format!("{:-^20}", key)).unwrap();
+        uncompressed.insert(key, format!("{:-^20}", key)).unwrap();
+    }
+    let _ = compressed.vacuum();
+    let _ = uncompressed.vacuum();
+    drop(compressed);
+    drop(uncompressed);
+
+    let btree = BTree::<u16, String>::create_with_store(CompressedStore::open(p, Lz).unwrap(), Options::default(), Comparator::natural()).unwrap();
+    let not_found = (0..300u16).step_by(3)
+        .filter(|key| btree.search(key) != Ok(format!("{:-^20}", key)))
+        .collect::<Vec<_>>();
+    let slots = btree.range(..).len();
+    let problems = btree.check();
+    drop(btree);
+    let sizes = (std::fs::metadata(p).unwrap().len(), std::fs::metadata(plain).unwrap().len());
+    let other_codec = CompressedStore::open(p, Other).err();
+    let _ = remove_file(p);
+    let _ = remove_file(plain);
+    assert_eq!(not_found, []);
+    assert_eq!(slots, 100);
+    assert_eq!(problems, []);
+    assert!(sizes.0 < sizes.1, "{:?}", sizes);
+    assert_eq!(other_codec, Some(Error::Io("compressed with lz".to_string())));
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn test_compressed_store_damaged_record() {
+    let p = "test_compressed_store_damaged_record";
+    let store = CompressedStore::open(p, Lz).unwrap();
+    for (page_id, byte) in [(0, 1), (1, 2), (0, 3)] {
+        if page_id == store.len() {
+            store.allocate();
+        }
+        store.write_page(page_id, &[byte; PAGE_SIZE]).unwrap();
+    }
+    drop(store);
+    let intact = std::fs::read(p).unwrap();
+    // records are the same length, a flipped bit in the last byte of the second one
+    let header_len = 4 + 1 + "lz".len();
+    let record_len = (intact.len() - header_len) / 3;
+    let mut damaged = intact.clone();
+    damaged[header_len + 2 * record_len - 1] ^= 1;
+    std::fs::write(p, damaged).unwrap();
+
+    let store = CompressedStore::open(p, Lz).unwrap();
+    let mut page = [0; PAGE_SIZE];
+    let first = store.read_page(0, &mut page).map(|_| page[0]).ok();
+    let second = store.read_page(1, &mut page).map_err(|error| error.kind());
+    let len = store.len();
+    drop(store);
+    let file_len = std::fs::metadata(p).unwrap().len() as usize;
+    let _ = remove_file(p);
+    assert_eq!(first, Some(1));
+    assert_eq!(second, Err(std::io::ErrorKind::UnexpectedEof));
+    assert_eq!(len, 1);
+    assert_eq!(file_len, header_len + record_len);
+}
+
+#[cfg(feature = "compression")]
+struct Other;
+
+#[cfg(feature = "compression")]
+impl Codec for Other {
+    fn name(&self) -> &'static str {
+        "other"
+    }
+
+    fn compress(&self, bytes: &[u8]) -> Vec<u8> {
+        bytes.to_vec()
+    }
+
+    fn decompress(&self, bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+        Ok(bytes.to_vec())
+    }
+}
+
+#[test]
+fn test_encrypted_store() {
+    let p = "test_encrypted_store";
+    let key = [7; 32];
+    let btree = BTree::<u16, String>::create_with_store(EncryptedStore::open(p, key).unwrap(), Options::default(), Comparator::natural()).unwrap();
+    for key in 0..200u16 {
+        btree.insert(key, format!("secret{}", key)).unwrap();
+    }
+    drop(btree);
+
+    let bytes = std::fs::read(p).unwrap();
+    let leaked = bytes.windows(6).any(|window| window == b"secret");
+    let wrong_key = EncryptedStore::open(p, [8; 32]).err();
+    let btree = BTree::<u16, String>::create_with_store(EncryptedStore::open(p, key).unwrap(), Options::default(), Comparator::natural()).unwrap();
+    let not_found = (0..200u16)
+        .filter(|key| btree.search(key) != Ok(format!("secret{}", key)))
+        .collect::<Vec<_>>();
+    let problems = btree.check();
+    drop(btree);
+
+    // a page copied over another one no longer opens
+    let store = EncryptedStore::open(p, key).unwrap();
+    let mut page = [0; PAGE_SIZE];
+    let before = store.read_page(2, &mut page).is_ok();
+    drop(store);
+    let mut tampered = std::fs::read(p).unwrap();
+    // a 64 byte header, then a counter, the page and a tag each
+    let record_len = 8 + PAGE_SIZE + 16;
+    tampered.copy_within(64 + record_len..64 + 2 * record_len, 64 + 2 * record_len);
+    std::fs::write(p, tampered).unwrap();
+    let store = EncryptedStore::open(p, key).unwrap();
+    let opened = page;
+    let after = store.read_page(2, &mut page).map_err(|error| error.kind());
+    drop(store);
+
+    // the tree reports the page instead of decoding what failed to open
+    let btree = BTree::<u16, String>::create_with_store(EncryptedStore::open(p, key).unwrap(), Options::default(), Comparator::natural()).unwrap();
+    let failed = (0..200u16)
+        .filter(|key| btree.search(key) == Err(Error::Io("page failed authentication".to_string())))
+        .count();
+    let tampered_problems = btree.check();
+    drop(btree);
+    let _ = remove_file(p);
+    assert!(!leaked);
+    assert_eq!(wrong_key, Some(Error::WrongKey));
+    assert_eq!(not_found, []);
+    assert_eq!(problems, []);
+    assert!(before);
+    assert_eq!(after, Err(std::io::ErrorKind::InvalidData));
+    assert_eq!(page, opened);
+    assert!(failed > 0);
+    assert!(tampered_problems.contains(&Problem::Unreadable { page_id: 2, error: "page failed authentication".to_string() }), "{:?}", tampered_problems);
+}
+
+#[test]
+fn test_encrypted_store_truncate() {
+    let p = "test_encrypted_store_truncate";
+    // a 64 byte header, then a counter, the page and a tag each
+    let counter = |page_id: usize| {
+        let bytes = std::fs::read(p).unwrap();
+        let offset = 64 + (8 + PAGE_SIZE + 16) * page_id;
+        let mut counter = [0; 8];
+        counter.copy_from_slice(&bytes[offset..offset + 8]);
+        u64::from_le_bytes(counter)
+    };
+    let store = EncryptedStore::open(p, [7; 32]).unwrap();
+    for page_id in 0..4 {
+        store.write_page(store.allocate(), &[page_id as u8; PAGE_SIZE]).unwrap();
+    }
+    let highest = counter(3);
+    store.truncate(1).unwrap();
+    drop(store);
+
+    let store = EncryptedStore::open(p, [7; 32]).unwrap();
+    store.write_page(store.allocate(), &[9; PAGE_SIZE]).unwrap();
+    let mut page = [0; PAGE_SIZE];
+    let read = store.read_page(1, &mut page).map(|_| page[0]).ok();
+    drop(store);
+    let rewritten = counter(1);
+    let _ = remove_file(p);
+    assert_eq!(read, Some(9));
+    assert!(rewritten > highest, "{} <= {}", rewritten, highest);
+}
+
+#[test]
+fn test_expiry() {
+    let p = "test_expiry";
+    let btree = BTree::<u16, Expiring<String>>::create(p);
+    for key in 0..100u16 {
+        let ttl = if key % 2 == 0 { Duration::from_secs(0) } else { Duration::from_secs(3600) };
+        btree.insert_with_ttl(key, format!("v{}", key), ttl).unwrap();
+    }
+    btree.insert(100, Expiring::new("forever".to_string())).unwrap();
+    // a new ttl replaces the expired entry instead of adding a second one
+    btree.insert_with_ttl(10, "again".to_string(), Duration::from_secs(3600)).unwrap();
+
+    let expired = btree.search(&20);
+    let update_expired = btree.update(&30, Expiring::new("late".to_string()));
+    let alive = btree.search(&21).map(|expiring| expiring.value);
+    let again = btree.search(&10).map(|expiring| expiring.value);
+    let forever = btree.search(&100).map(|expiring| expiring.value);
+    let scanned = btree.range(..).len();
+    let stored = btree.stats().keys;
+    let purged = btree.purge_expired();
+    let stored_after = btree.stats().keys;
+    let problems = btree.check();
+    let _ = remove_file(p);
+    assert_eq!(expired, Err(Error::NotFound));
+    assert_eq!(update_expired, Err(Error::NotFound));
+    assert_eq!(alive, Ok("v21".to_string()));
+    assert_eq!(again, Ok("again".to_string()));
+    assert_eq!(forever, Ok("forever".to_string()));
+    assert_eq!(scanned, 52);
+    assert_eq!(stored, 101);
+    assert_eq!(purged, 49);
+    assert_eq!(stored_after, 52);
+    assert_eq!(problems, []);
+}
@@ -0,0 +1,136 @@
+use std::fmt::Debug;
+use std::io;
+
+use crate::btree::BTree;
+use crate::error::Error;
+use crate::leaf::Leaf;
+use crate::node::Node;
+use crate::page::PAGE_SIZE;
+use crate::page::Page;
+use crate::slot::Slot;
+use crate::slot::SlotBytes;
+use crate::store::io_error;
+
+
+// a page of the rebuilt tree and the keys it spans
+struct Built<K> {
+    page_id: u16,
+    first: K,
+    last: K,
+}
+
+impl<K, V> BTree<K, V>
+    where K: SlotBytes + Clone + Debug,
+          V: SlotBytes + Clone + Debug,
+{
+    pub fn vacuum(&self) -> Result<u16, Error> {
+        self.vacuum_with_fill(1.0)
+    }
+
+    // rewrites the tree on the lowest free pages, leaves filled up to fill, a share of the page, and branches
+    // as far as they go; returns how many pages were cut off the end of the file.
+    // pages are overwritten in place, a crash halfway loses the tree unless it is OnFlush
+    pub fn vacuum_with_fill(&self, fill: f64) -> Result<u16, Error> {
+        let _latch = self.latch.write().unwrap();
+        let _writing = self.storage.begin_write();
+        self.rebuild(|slots| slots, fill).map_err(io_error)
+    }
+
+    // bottom-up from the slots edit makes of the current ones, which must stay in key order without duplicates;
+    // the caller holds the tree latch and the storage write guard
+    pub(super) fn rebuild(&self, edit: impl FnOnce(Vec<(K, V)>) -> Vec<(K, V)>, fill: f64) -> io::Result<u16> {
+        let mut page_ids = vec![];
+        let mut slots = vec![];
+        if let Some(root_page_id) = self.root_page_id() {
+            self.collect(root_page_id, &mut page_ids, &mut slots)?;
+        }
+        let slots = edit(slots);
+        self.storage.reuse_pages(&page_ids, |allocate| {
+            let mut level = self.build_leaves(slots, fill, allocate)?;
+            while level.len() > 1 {
+                level = self.build_branches(level, allocate)?;
+            }
+            self.set_root_page_id(level.first().map_or(0, |root| root.page_id))
+        })
+    }
+
+    fn collect(&self, page_id: u16, page_ids: &mut Vec<u16>, slots: &mut Vec<(K, V)>) -> io::Result<()> {
+        page_ids.push(page_id);
+        match self.read_node(page_id)? {
+            Node::Leaf(leaf) => slots.extend(leaf.slotted.slots()),
+            Node::Branch(branch) => {
+                for (_, child_page_id) in branch.slotted.slots() {
+                    self.collect(child_page_id, page_ids, slots)?;
+                }
+                self.collect(branch.max_page_id(), page_ids, slots)?;
+            },
+        }
+        Ok(())
+    }
+
+    fn build_leaves(&self, slots: Vec<(K, V)>, fill: f64, allocate: &mut dyn FnMut() -> u16) -> io::Result<Vec<Built<K>>> {
+        let mut built = vec![];
+        let mut current: Option<(Leaf<K, V>, Built<K>)> = None;
+        for (key, value) in slots {
+            let slot = Slot::new(key.clone(), value);
+            if let Some((leaf, span)) = current.as_mut() {
+                if !leaf.slotted.is_full(&slot) {
+                    let _ = leaf.slotted.insert(&slot);
+                    // the prefix grows to what every key so far shares
+                    leaf.slotted.compact();
+                    if (PAGE_SIZE - leaf.slotted.free_space()) as f64 <= fill * PAGE_SIZE as f64 {
+                        span.last = key;
+                        continue;
+                    }
+                    let _ = leaf.slotted.delete(&key);
+                    leaf.slotted.compact();
+                }
+            }
+            if let Some((mut leaf, span)) = current.take() {
+                self.write_leaf(&mut leaf)?;
+                built.push(span);
+            }
+            let mut leaf = self.create_leaf(Page::new(allocate()));
+            let _ = leaf.slotted.insert(&slot);
+            let span = Built { page_id: leaf.slotted.page.id, first: key.clone(), last: key };
+            current = Some((leaf, span));
+        }
+        if let Some((mut leaf, span)) = current {
+            self.write_leaf(&mut leaf)?;
+            built.push(span);
+        }
+        Ok(built)
+    }
+
+    // the last child of each branch is its max page
+    fn build_branches(&self, children: Vec<Built<K>>, allocate: &mut dyn FnMut() -> u16) -> io::Result<Vec<Built<K>>> {
+        let mut built = vec![];
+        let mut children = children.into_iter();
+        let first_child = match children.next() {
+            Some(child) => child,
+            None => return Ok(built),
+        };
+        let mut branch = self.create_branch(Page::new(allocate()));
+        let mut first = first_child.first.clone();
+        let mut max_child = first_child;
+        for child in children {
+            let separator = (self.comparator.separator)(&max_child.last, &child.first);
+            let slot = Slot::new(separator, max_child.page_id);
+            if !branch.slotted.is_full(&slot) {
+                let _ = branch.slotted.insert(&slot);
+                max_child = child;
+                continue;
+            }
+            branch.set_max_page_id(max_child.page_id);
+            self.storage.write_page(&mut branch.slotted.page)?;
+            built.push(Built { page_id: branch.slotted.page.id, first, last: max_child.last });
+            branch = self.create_branch(Page::new(allocate()));
+            first = child.first.clone();
+            max_child = child;
+        }
+        branch.set_max_page_id(max_child.page_id);
+        self.storage.write_page(&mut branch.slotted.page)?;
+        built.push(Built { page_id: branch.slotted.page.id, first, last: max_child.last });
+        Ok(built)
+    }
+}
@@ -0,0 +1,221 @@
+// chacha20-poly1305 as in rfc 8439, small enough to keep the crate free of dependencies
+
+pub const KEY_LEN: usize = 32;
+pub const NONCE_LEN: usize = 12;
+pub const TAG_LEN: usize = 16;
+
+pub type Key = [u8; KEY_LEN];
+pub type Nonce = [u8; NONCE_LEN];
+pub type Tag = [u8; TAG_LEN];
+
+// encrypts bytes in place and returns the tag over them and ad
+pub fn seal(key: &Key, nonce: &Nonce, ad: &[u8], bytes: &mut [u8]) -> Tag {
+    xor_key_stream(key, nonce, 1, bytes);
+    poly1305(&poly1305_key(key, nonce), &mac_data(ad, bytes))
+}
+
+// decrypts bytes in place, false leaves them as they were when the tag does not match
+pub fn open(key: &Key, nonce: &Nonce, ad: &[u8], bytes: &mut [u8], tag: &Tag) -> bool {
+    let expected = poly1305(&poly1305_key(key, nonce), &mac_data(ad, bytes));
+    // every byte is compared, so the time taken tells nothing about the tag
+    if expected.iter().zip(tag).fold(0, |difference, (a, b)| difference | (a ^ b)) != 0 {
+        return false;
+    }
+    xor_key_stream(key, nonce, 1, bytes);
+    true
+}
+
+fn poly1305_key(key: &Key, nonce: &Nonce) -> [u8; 32] {
+    let mut poly1305_key = [0; 32];
+    poly1305_key.copy_from_slice(&chacha20_block(key, 0, nonce)[..32]);
+    poly1305_key
+}
+
+fn mac_data(ad: &[u8], ciphertext: &[u8]) -> Vec<u8> {
+    let mut data = ad.to_vec();
+    data.resize(data.len().div_ceil(16) * 16, 0);
+    data.extend_from_slice(ciphertext);
+    data.resize(data.len().div_ceil(16) * 16, 0);
+    data.extend_from_slice(&(ad.len() as u64).to_le_bytes());
+    data.extend_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+    data
+}
+
+fn xor_key_stream(key: &Key, nonce: &Nonce, counter: u32, bytes: &mut [u8]) {
+    for (index, chunk) in bytes.chunks_mut(64).enumerate() {
+        let block = chacha20_block(key, counter + index as u32, nonce);
+        chunk.iter_mut().zip(block.iter()).for_each(|(byte, key_byte)| *byte ^= key_byte);
+    }
+}
+
+fn chacha20_block(key: &Key, counter: u32, nonce: &Nonce) -> [u8; 64] {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+    for (index, word) in key.chunks(4).enumerate() {
+        state[4 + index] = le32(word);
+    }
+    state[12] = counter;
+    for (index, word) in nonce.chunks(4).enumerate() {
+        state[13 + index] = le32(word);
+    }
+    let mut working = state;
+    for _ in 0..10 {
+        quarter_round(&mut working, 0, 4, 8, 12);
+        quarter_round(&mut working, 1, 5, 9, 13);
+        quarter_round(&mut working, 2, 6, 10, 14);
+        quarter_round(&mut working, 3, 7, 11, 15);
+        quarter_round(&mut working, 0, 5, 10, 15);
+        quarter_round(&mut working, 1, 6, 11, 12);
+        quarter_round(&mut working, 2, 7, 8, 13);
+        quarter_round(&mut working, 3, 4, 9, 14);
+    }
+    let mut block = [0; 64];
+    for index in 0..16 {
+        block[4 * index..4 * index + 4].copy_from_slice(&working[index].wrapping_add(state[index]).to_le_bytes());
+    }
+    block
+}
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+// 26 bit limbs, so the products fit in u64
+fn poly1305(key: &[u8; 32], message: &[u8]) -> Tag {
+    const MASK: u32 = 0x3ffffff;
+    let r = [
+        le32(&key[0..4]) & 0x3ffffff,
+        (le32(&key[3..7]) >> 2) & 0x3ffff03,
+        (le32(&key[6..10]) >> 4) & 0x3ffc0ff,
+        (le32(&key[9..13]) >> 6) & 0x3f03fff,
+        (le32(&key[12..16]) >> 8) & 0x00fffff,
+    ];
+    let s = [r[1] * 5, r[2] * 5, r[3] * 5, r[4] * 5];
+    let mut h = [0u32; 5];
+
+    for chunk in message.chunks(16) {
+        let mut block = [0; 17];
+        block[..chunk.len()].copy_from_slice(chunk);
+        block[chunk.len()] = 1;
+        let high_bit = (block[16] as u32) << 24;
+        h[0] += le32(&block[0..4]) & MASK;
+        h[1] += (le32(&block[3..7]) >> 2) & MASK;
+        h[2] += (le32(&block[6..10]) >> 4) & MASK;
+        h[3] += (le32(&block[9..13]) >> 6) & MASK;
+        h[4] += (le32(&block[12..16]) >> 8) | high_bit;
+
+        let [h0, h1, h2, h3, h4] = h.map(|limb| limb as u64);
+        let [r0, r1, r2, r3, r4] = r.map(|limb| limb as u64);
+        let [s1, s2, s3, s4] = s.map(|limb| limb as u64);
+        let mut d = [
+            h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1,
+            h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2,
+            h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3,
+            h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4,
+            h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0,
+        ];
+        for index in 0..4 {
+            d[index + 1] += d[index] >> 26;
+            h[index] = d[index] as u32 & MASK;
+        }
+        h[4] = d[4] as u32 & MASK;
+        h[0] += (d[4] >> 26) as u32 * 5;
+        h[1] += h[0] >> 26;
+        h[0] &= MASK;
+    }
+
+    // fully carried, then h - p when that does not go below zero
+    for index in 1..5 {
+        h[index] += h[index - 1] >> 26;
+        h[index - 1] &= MASK;
+    }
+    h[0] += (h[4] >> 26) * 5;
+    h[4] &= MASK;
+    h[1] += h[0] >> 26;
+    h[0] &= MASK;
+    let mut g = [0u32; 5];
+    let mut carry = 5;
+    for index in 0..5 {
+        g[index] = h[index] + carry;
+        carry = g[index] >> 26;
+        g[index] &= MASK;
+    }
+    g[4] = g[4].wrapping_sub(1 << 26).wrapping_add(carry << 26);
+    let use_g = (g[4] >> 31).wrapping_sub(1);
+    for index in 0..5 {
+        h[index] = (h[index] & !use_g) | (g[index] & use_g);
+    }
+
+    let words = [
+        h[0] | (h[1] << 26),
+        (h[1] >> 6) | (h[2] << 20),
+        (h[2] >> 12) | (h[3] << 14),
+        (h[3] >> 18) | (h[4] << 8),
+    ];
+    let mut tag = [0; TAG_LEN];
+    let mut carry = 0u64;
+    for index in 0..4 {
+        let sum = words[index] as u64 + le32(&key[16 + 4 * index..20 + 4 * index]) as u64 + carry;
+        tag[4 * index..4 * index + 4].copy_from_slice(&(sum as u32).to_le_bytes());
+        carry = sum >> 32;
+    }
+    tag
+}
+
+fn le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::TryInto;
+
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        let text = text.replace([' ', ':'], "");
+        (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+    }
+
+    // the test vectors of rfc 8439
+    #[test]
+    fn test_chacha20_block() {
+        let key = (0..32u8).collect::<Vec<_>>().try_into().unwrap();
+        let nonce = hex("000000090000004a00000000").try_into().unwrap();
+        let block = chacha20_block(&key, 1, &nonce);
+        assert_eq!(block[..16], hex("10f1e7e4d13b5915500fdd1fa32071c4")[..]);
+    }
+
+    #[test]
+    fn test_poly1305() {
+        let key = hex("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b").try_into().unwrap();
+        let tag = poly1305(&key, b"Cryptographic Forum Research Group");
+        assert_eq!(tag[..], hex("a8061dc1305136c6c22b8baf0c0127a9")[..]);
+    }
+
+    #[test]
+    fn test_seal_open() {
+        let key = (0x80..0xa0u8).collect::<Vec<_>>().try_into().unwrap();
+        let nonce = hex("070000004041424344454647").try_into().unwrap();
+        let ad = hex("50515253c0c1c2c3c4c5c6c7");
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+        let mut bytes = plaintext.to_vec();
+        let tag = seal(&key, &nonce, &ad, &mut bytes);
+        assert_eq!(bytes[..16], hex("d31a8d34648e60db7b86afbc53ef7ec2")[..]);
+        assert_eq!(tag[..], hex("1ae10b594f09e26a7e902ecbd0600691")[..]);
+
+        let mut tampered = bytes.clone();
+        tampered[0] ^= 1;
+        assert!(!open(&key, &nonce, &ad, &mut tampered, &tag));
+        assert!(!open(&key, &nonce, b"other", &mut bytes.clone(), &tag));
+        assert!(open(&key, &nonce, &ad, &mut bytes, &tag));
+        assert_eq!(bytes, plaintext.to_vec());
+    }
+}
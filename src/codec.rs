@@ -0,0 +1,127 @@
+use std::io;
+
+
+// name is kept in the header of a compressed file, so it must stay stable across releases
+pub trait Codec: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn compress(&self, bytes: &[u8]) -> Vec<u8>;
+    fn decompress(&self, bytes: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = MIN_MATCH + 0x7F;
+const MAX_LITERALS: usize = 0x80;
+const WINDOW: usize = 1 << 12;
+
+// lz77 with byte tokens: 0x00..0x7F is a run of 1 to 128 literals that follow,
+// 0x80..0xFF a match of 3 to 130 bytes at the u16 distance that follows
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lz;
+
+impl Codec for Lz {
+    fn name(&self) -> &'static str {
+        "lz"
+    }
+
+    fn compress(&self, bytes: &[u8]) -> Vec<u8> {
+        let mut compressed = vec![];
+        let mut literals = 0..0;
+        let mut position = 0;
+        while position < bytes.len() {
+            match longest_match(bytes, position) {
+                Some((distance, len)) => {
+                    push_literals(&mut compressed, &bytes[literals]);
+                    compressed.push(0x80 | (len - MIN_MATCH) as u8);
+                    compressed.extend_from_slice(&(distance as u16).to_le_bytes());
+                    position += len;
+                    literals = position..position;
+                },
+                None => {
+                    position += 1;
+                    literals.end = position;
+                },
+            }
+        }
+        push_literals(&mut compressed, &bytes[literals]);
+        compressed
+    }
+
+    fn decompress(&self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        let mut decompressed: Vec<u8> = vec![];
+        let mut position = 0;
+        while position < bytes.len() {
+            let token = bytes[position] as usize;
+            position += 1;
+            if token < 0x80 {
+                let literals = bytes.get(position..position + token + 1).ok_or_else(invalid)?;
+                decompressed.extend_from_slice(literals);
+                position += token + 1;
+            } else {
+                let distance = bytes.get(position..position + 2).ok_or_else(invalid)?;
+                let distance = u16::from_le_bytes([distance[0], distance[1]]) as usize;
+                position += 2;
+                if distance == 0 || distance > decompressed.len() {
+                    return Err(invalid());
+                }
+                // a match may overlap the bytes it produces, so it is copied byte by byte
+                let start = decompressed.len() - distance;
+                for index in 0..(token & 0x7F) + MIN_MATCH {
+                    decompressed.push(decompressed[start + index]);
+                }
+            }
+        }
+        Ok(decompressed)
+    }
+}
+
+// the longest earlier occurrence within the window, searched from the nearest
+fn longest_match(bytes: &[u8], position: usize) -> Option<(usize, usize)> {
+    let max_len = MAX_MATCH.min(bytes.len() - position);
+    let mut best: Option<(usize, usize)> = None;
+    for start in (position.saturating_sub(WINDOW)..position).rev() {
+        let len = (0..max_len).take_while(|&index| bytes[start + index] == bytes[position + index]).count();
+        if len >= MIN_MATCH && best.is_none_or(|(_, best_len)| len > best_len) {
+            best = Some((position - start, len));
+            if len == max_len {
+                break;
+            }
+        }
+    }
+    best
+}
+
+fn push_literals(compressed: &mut Vec<u8>, literals: &[u8]) {
+    for chunk in literals.chunks(MAX_LITERALS) {
+        compressed.push((chunk.len() - 1) as u8);
+        compressed.extend_from_slice(chunk);
+    }
+}
+
+fn invalid() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "corrupt compressed page")
+}
+
+#[cfg(test)]
+mod test {
+    use super::Codec;
+    use super::Lz;
+
+    #[test]
+    fn test_lz_round_trip() {
+        let inputs: Vec<Vec<u8>> = vec![
+            vec![],
+            b"a".to_vec(),
+            b"abcabcabcabcabcabc".to_vec(),
+            vec![0; 300],
+            (0..=255u8).collect(),
+            b"user_alice user_bob user_carol user_dave".repeat(8),
+        ];
+        for input in inputs {
+            let compressed = Lz.compress(&input);
+            assert_eq!(Lz.decompress(&compressed).unwrap(), input);
+        }
+        assert!(Lz.compress(&[0; 64]).len() < 8);
+        assert!(Lz.decompress(&[0x80, 1, 0]).is_err());
+        assert!(Lz.decompress(&[0x05, 1]).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::fmt::Error;
+use std::fmt::Formatter;
+
+use crate::slot::SlotBytes;
+
+
+pub type Compare<K> = fn(&K, &K) -> Ordering;
+
+// name is kept in the meta page, so it must stay stable across releases
+pub struct Comparator<K> {
+    pub name: &'static str,
+    pub compare: Compare<K>,
+    // must never return a key longer than upper, branches reserve room by the longest key
+    pub separator: fn(&K, &K) -> K,
+}
+
+impl<K: Clone> Comparator<K> {
+    pub fn new(name: &'static str, compare: Compare<K>) -> Self {
+        Comparator { name, compare, separator: upper_key }
+    }
+
+    pub fn with_separator(mut self, separator: fn(&K, &K) -> K) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    pub fn less(&self, a: &K, b: &K) -> bool {
+        (self.compare)(a, b) == Ordering::Less
+    }
+}
+
+impl<K: Ord + SlotBytes + Clone> Comparator<K> {
+    pub fn natural() -> Self {
+        Comparator { name: NATURAL, compare: K::cmp, separator: K::separator }
+    }
+
+    pub fn reverse() -> Self {
+        Comparator::new("reverse", |a: &K, b: &K| b.cmp(a))
+    }
+}
+
+impl Comparator<String> {
+    pub fn case_insensitive() -> Self {
+        Comparator::new("case_insensitive", |a: &String, b: &String| case_insensitive(a, b))
+            .with_separator(|lower: &String, upper: &String| prefix_separator(lower, upper, case_insensitive))
+    }
+
+    // "file2" < "file10"
+    pub fn numeric() -> Self {
+        Comparator::new("numeric", |a: &String, b: &String| numeric(a, b))
+            .with_separator(|lower: &String, upper: &String| prefix_separator(lower, upper, numeric))
+    }
+
+    // case and punctuation only break ties: "Coop" < "co-op" < "coop" < "cooperate"
+    pub fn dictionary() -> Self {
+        Comparator::new("dictionary", |a: &String, b: &String| dictionary(a, b))
+            .with_separator(|lower: &String, upper: &String| prefix_separator(lower, upper, dictionary))
+    }
+}
+
+impl<K> Clone for Comparator<K> {
+    fn clone(&self) -> Self {
+        Comparator { name: self.name, compare: self.compare, separator: self.separator }
+    }
+}
+
+impl<K> Debug for Comparator<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "Comparator({})", self.name)
+    }
+}
+
+pub const NATURAL: &str = "natural";
+
+fn upper_key<K: Clone>(_lower: &K, upper: &K) -> K {
+    upper.clone()
+}
+
+// shortest prefix of upper that sorts after lower; valid for orders where a prefix never sorts after the whole string
+fn prefix_separator(lower: &str, upper: &str, compare: fn(&str, &str) -> Ordering) -> String {
+    upper.char_indices()
+        .map(|(i, c)| &upper[..i + c.len_utf8()])
+        .find(|prefix| compare(lower, prefix) == Ordering::Less)
+        .unwrap_or(upper)
+        .to_string()
+}
+
+fn case_insensitive(a: &str, b: &str) -> Ordering {
+    let a = a.chars().flat_map(char::to_lowercase);
+    let b = b.chars().flat_map(char::to_lowercase);
+    a.cmp(b)
+}
+
+fn numeric(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+    loop {
+        match (a_chars.peek(), b_chars.peek()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_digits(&mut a_chars);
+                let y = take_digits(&mut b_chars);
+                let x = x.trim_start_matches('0');
+                let y = y.trim_start_matches('0');
+                match x.len().cmp(&y.len()).then_with(|| x.cmp(y)) {
+                    Ordering::Equal => continue,
+                    ordering => return ordering,
+                }
+            },
+            (Some(x), Some(y)) => {
+                match x.cmp(y) {
+                    Ordering::Equal => {
+                        a_chars.next();
+                        b_chars.next();
+                    },
+                    ordering => return ordering,
+                }
+            },
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+        digits.push(*c);
+        chars.next();
+    }
+    digits
+}
+
+fn dictionary(a: &str, b: &str) -> Ordering {
+    let primary = |s: &str| s.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect::<Vec<_>>();
+    primary(a).cmp(&primary(b)).then_with(|| a.cmp(b))
+}
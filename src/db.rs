@@ -0,0 +1,159 @@
+#[cfg(test)] mod test;
+
+use std::fmt::Debug;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::btree::BTree;
+use crate::btree::ReadOnlyBTree;
+use crate::comparator::Comparator;
+use crate::error::Error;
+use crate::meta::Meta;
+use crate::node::Node;
+use crate::options::Durability;
+use crate::options::Options;
+use crate::page::Page;
+use crate::slot::SlotBytes;
+use crate::storage::Storage;
+use crate::store::FileStore;
+use crate::store::PageStore;
+use crate::store::io_error;
+
+
+// the catalog is a tree of its own whose meta is page 0, it maps tree names to their meta pages
+pub struct Db {
+    storage: Arc<Storage>,
+    catalog: BTree<String, u16>,
+    // create and drop look the name up before they change the catalog
+    catalog_latch: Mutex<()>,
+}
+
+impl Db {
+    pub fn open(file_path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::open_store(FileStore::open(file_path)?)
+    }
+
+    // shares the lock with other readers, trees are opened with open_tree_read_only
+    pub fn open_read_only(file_path: impl AsRef<Path>) -> Result<Self, Error> {
+        let store = FileStore::open_read_only(file_path)?;
+        if store.is_empty() {
+            return Err(Error::NoPage);
+        }
+        Self::open_store_with(store, Durability::Never)
+    }
+
+    pub fn open_store(store: impl PageStore + 'static) -> Result<Self, Error> {
+        Self::open_store_with(store, Durability::default())
+    }
+
+    pub fn open_store_with(store: impl PageStore + 'static, durability: Durability) -> Result<Self, Error> {
+        let storage = Arc::new(Storage::new(Box::new(store), durability).map_err(io_error)?);
+        let catalog = if storage.next_page_id() > 0 {
+            BTree::open_in(Arc::clone(&storage), 0, Options::default(), Comparator::natural())?
+        } else {
+            BTree::create_in(Arc::clone(&storage), Options::default(), Comparator::natural())?
+        };
+        Ok(Db { storage, catalog, catalog_latch: Mutex::new(()) })
+    }
+
+    pub fn create_tree<K, V>(&self, name: &str) -> Result<BTree<K, V>, Error>
+        where K: Ord + SlotBytes + Clone + Debug,
+              V: SlotBytes + Clone + Debug,
+    {
+        self.create_tree_with(name, Options::default(), Comparator::natural())
+    }
+
+    pub fn create_tree_with<K, V>(&self, name: &str, options: Options, comparator: Comparator<K>) -> Result<BTree<K, V>, Error>
+        where K: SlotBytes + Debug,
+              V: SlotBytes + Clone + Debug,
+    {
+        let _latch = self.catalog_latch.lock().unwrap();
+        if self.catalog.search(&name.to_string()).is_ok() {
+            return Err(Error::TreeExists(name.to_string()));
+        }
+        let btree = BTree::create_in(Arc::clone(&self.storage), options, comparator)?;
+        self.catalog.insert(name.to_string(), btree.meta_page_id())?;
+        Ok(btree)
+    }
+
+    // every call makes a new handle with its own root and latch, share one handle between threads instead
+    pub fn open_tree<K, V>(&self, name: &str) -> Result<BTree<K, V>, Error>
+        where K: Ord + SlotBytes + Clone + Debug,
+              V: SlotBytes + Clone + Debug,
+    {
+        self.open_tree_with(name, Comparator::natural())
+    }
+
+    pub fn open_tree_with<K, V>(&self, name: &str, comparator: Comparator<K>) -> Result<BTree<K, V>, Error>
+        where K: SlotBytes + Debug,
+              V: SlotBytes + Clone + Debug,
+    {
+        let _latch = self.catalog_latch.lock().unwrap();
+        let meta_page_id = self.catalog.search(&name.to_string())?;
+        BTree::open_in(Arc::clone(&self.storage), meta_page_id, Options::default(), comparator)
+    }
+
+    pub fn open_tree_read_only<K, V>(&self, name: &str) -> Result<ReadOnlyBTree<K, V>, Error>
+        where K: Ord + SlotBytes + Clone + Debug,
+              V: SlotBytes + Clone + Debug,
+    {
+        self.open_tree_read_only_with(name, Comparator::natural())
+    }
+
+    pub fn open_tree_read_only_with<K, V>(&self, name: &str, comparator: Comparator<K>) -> Result<ReadOnlyBTree<K, V>, Error>
+        where K: SlotBytes + Debug,
+              V: SlotBytes + Clone + Debug,
+    {
+        self.open_tree_with(name, comparator).map(ReadOnlyBTree::new)
+    }
+
+    // refused while a handle on the tree is open, its pages go back to the free list
+    pub fn drop_tree(&self, name: &str) -> Result<(), Error> {
+        let _latch = self.catalog_latch.lock().unwrap();
+        let meta_page_id = self.catalog.search(&name.to_string())?;
+        if self.storage.has_handles(meta_page_id) {
+            return Err(Error::TreeInUse(name.to_string()));
+        }
+        self.catalog.delete(&name.to_string());
+        let _writing = self.storage.begin_write();
+
+        let mut page = Page::new(meta_page_id);
+        self.storage.read_page(&mut page).map_err(io_error)?;
+        let root_page_id = Meta::new(page).root_page_id();
+        if root_page_id != 0 {
+            for page_id in self.page_ids(root_page_id).map_err(io_error)? {
+                self.storage.free_page(page_id).map_err(io_error)?;
+            }
+        }
+        self.storage.free_page(meta_page_id).map_err(io_error)
+    }
+
+    pub fn flush(&self) -> Result<(), Error> {
+        self.storage.flush().map_err(io_error)
+    }
+
+    pub fn tree_names(&self) -> Vec<String> {
+        self.catalog.range(..).into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>()
+    }
+
+    // keys are only compared as bytes, a walk never needs the order of the tree
+    fn page_ids(&self, page_id: u16) -> io::Result<Vec<u16>> {
+        let mut page = Page::new(page_id);
+        self.storage.read_page(&mut page)?;
+        match Node::<Vec<u8>, Vec<u8>>::new(page, Vec::cmp) {
+            Node::Leaf(_) => Ok(vec![page_id]),
+            Node::Branch(branch) => {
+                let mut page_ids = vec![page_id];
+                for (_, child_page_id) in branch.slotted.slots() {
+                    page_ids.append(&mut self.page_ids(child_page_id)?);
+                }
+                page_ids.append(&mut self.page_ids(branch.max_page_id())?);
+                Ok(page_ids)
+            },
+        }
+    }
+}
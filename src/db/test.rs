@@ -0,0 +1,141 @@
+use std::fs::remove_file;
+
+use crate::comparator::Comparator;
+use crate::db::Db;
+use crate::error::Error;
+use crate::options::Options;
+
+
+#[test]
+fn test_create_open_tree() {
+    let p = "test_create_open_tree";
+    let db = Db::open(p).unwrap();
+    let users = db.create_tree::<u16, String>("users").unwrap();
+    let tags = db.create_tree_with::<String, u16>("tags", Options::default(), Comparator::case_insensitive()).unwrap();
+    for key in 0..30u16 {
+        users.insert(key, format!("user{}", key)).unwrap();
+        tags.insert(format!("Tag{}", key), key).unwrap();
+    }
+    drop(users);
+    drop(tags);
+    drop(db);
+
+    let db = Db::open(p).unwrap();
+    let users = db.open_tree::<u16, String>("users").unwrap();
+    let tags = db.open_tree_with::<String, u16>("tags", Comparator::case_insensitive()).unwrap();
+    let names = db.tree_names();
+    let exists = db.create_tree::<u16, String>("users").err();
+    let missing = db.open_tree::<u16, String>("groups").err();
+    let _ = remove_file(p);
+    assert_eq!(names, ["tags", "users"]);
+    assert_eq!(users.search(&17), Ok("user17".to_string()));
+    assert_eq!(tags.search(&"TAG17".to_string()), Ok(17));
+    assert_eq!(exists, Some(Error::TreeExists("users".to_string())));
+    assert_eq!(missing, Some(Error::NotFound));
+}
+
+#[test]
+fn test_drop_tree() {
+    let p = "test_drop_tree";
+    let db = Db::open(p).unwrap();
+    let users = db.create_tree::<u16, String>("users").unwrap();
+    for key in 0..100u16 {
+        users.insert(key, format!("user{}", key)).unwrap();
+    }
+    let in_use = db.drop_tree("users");
+    drop(users);
+    let used_pages = db.storage.next_page_id();
+
+    assert_eq!(db.drop_tree("users"), Ok(()));
+    let free_pages = db.storage.free_page_ids().unwrap().len();
+    let groups = db.create_tree::<u16, String>("groups").unwrap();
+    for key in 0..100u16 {
+        groups.insert(key, format!("group{}", key)).unwrap();
+    }
+    drop(groups);
+    drop(db);
+
+    let db = Db::open(p).unwrap();
+    let groups = db.open_tree::<u16, String>("groups").unwrap();
+    let not_found = (0..100u16)
+        .filter(|key| groups.search(key) != Ok(format!("group{}", key)))
+        .collect::<Vec<_>>();
+    let next_page_id = db.storage.next_page_id();
+    let _ = remove_file(p);
+    assert_eq!(in_use, Err(Error::TreeInUse("users".to_string())));
+    assert!(free_pages > 10);
+    assert!(next_page_id <= used_pages + 1);
+    assert_eq!(db.tree_names(), ["groups"]);
+    assert_eq!(db.drop_tree("users"), Err(Error::NotFound));
+    assert_eq!(not_found, []);
+}
+
+#[test]
+fn test_vacuum_tree() {
+    let p = "test_vacuum_tree";
+    let db = Db::open(p).unwrap();
+    let users = db.create_tree::<u16, String>("users").unwrap();
+    let groups = db.create_tree::<u16, String>("groups").unwrap();
+    for key in 0..200u16 {
+        users.insert(key, format!("user{}", key)).unwrap();
+        groups.insert(key, format!("group{}", key)).unwrap();
+    }
+    for key in 0..200u16 {
+        if key % 20 != 0 {
+            users.delete(&key);
+        }
+    }
+    let free_before = db.storage.free_page_ids().unwrap().len();
+    assert!(users.vacuum().is_ok());
+    let free_after = db.storage.free_page_ids().unwrap().len();
+    let user_slots = users.range(..).len();
+    let group_slots = groups.range(..).len();
+    let problems = (users.check(), groups.check());
+    let _ = remove_file(p);
+    assert!(free_after > free_before);
+    assert_eq!(user_slots, 10);
+    assert_eq!(group_slots, 200);
+    assert_eq!(problems, (vec![], vec![]));
+}
+
+#[test]
+fn test_snapshots_of_two_trees() {
+    let p = "test_snapshots_of_two_trees";
+    let db = Db::open(p).unwrap();
+    let a = db.create_tree::<u16, u16>("a").unwrap();
+    let b = db.create_tree::<u16, u16>("b").unwrap();
+    for key in 0..50u16 {
+        a.insert(key, key).unwrap();
+    }
+    b.insert(1, 100).unwrap();
+
+    let a_snapshot = a.snapshot();
+    b.update(&1, 200).unwrap();
+    let b_snapshot = b.snapshot();
+    b.update(&1, 300).unwrap();
+    let values = (a_snapshot.search(&1), b_snapshot.search(&1), b.search(&1));
+    drop(a_snapshot);
+    drop(b_snapshot);
+    let _ = remove_file(p);
+    assert_eq!(values, (Ok(1), Ok(200), Ok(300)));
+}
+
+#[test]
+fn test_open_read_only() {
+    let p = "test_db_open_read_only";
+    let db = Db::open(p).unwrap();
+    let users = db.create_tree::<u16, String>("users").unwrap();
+    users.insert(1, "one".to_string()).unwrap();
+    let while_written = Db::open_read_only(p).err();
+    drop(users);
+    drop(db);
+
+    let readers = (Db::open_read_only(p).unwrap(), Db::open_read_only(p).unwrap());
+    let found = readers.0.open_tree_read_only::<u16, String>("users").unwrap().search(&1);
+    let writer = Db::open(p).err();
+    drop(readers);
+    let _ = remove_file(p);
+    assert_eq!(while_written, Some(Error::Locked));
+    assert_eq!(found, Ok("one".to_string()));
+    assert_eq!(writer, Some(Error::Locked));
+}
@@ -3,4 +3,18 @@ pub enum Error {
     NoPage,
     NotFound,
     FullLeaf,
+    // the entry does not fit in a leaf, or its key three times in a branch
+    TooLarge,
+    ComparatorMismatch(String),
+    TreeExists(String),
+    // a handle on the tree is still open
+    TreeInUse(String),
+    UniqueViolation,
+    // another handle, in this process or another, has the file open
+    Locked,
+    // the key does not open the encrypted file
+    WrongKey,
+    Io(String),
+    // line of the input and what is wrong with it
+    Parse(usize, String),
 }
\ No newline at end of file
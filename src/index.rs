@@ -0,0 +1,256 @@
+#[cfg(test)] mod test;
+
+use std::cmp::Ordering;
+use std::convert::TryInto;
+use std::fmt::Debug;
+use std::ops::Bound;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::btree::BTree;
+use crate::btree::ReadOnlyBTree;
+use crate::error::Error;
+use crate::slot::SlotBytes;
+
+
+// key of a non-unique index: the index key followed by the primary key, so equal index keys stay apart
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct IndexKey<IK, K> {
+    pub index_key: IK,
+    primary_key: Position<K>,
+}
+
+// Before and After only appear in range bounds, never on a page
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Position<K> { Before, At(K), After }
+
+impl<IK, K> IndexKey<IK, K> {
+    pub fn new(index_key: IK, primary_key: K) -> Self {
+        IndexKey { index_key, primary_key: Position::At(primary_key) }
+    }
+
+    pub fn primary_key(&self) -> Option<&K> {
+        match &self.primary_key {
+            Position::At(primary_key) => Some(primary_key),
+            _ => None,
+        }
+    }
+}
+
+impl<IK: SlotBytes, K: SlotBytes> SlotBytes for IndexKey<IK, K> {
+    fn into_bytes(&self) -> Vec<u8> {
+        let index_key = self.index_key.into_bytes();
+        let mut bytes = (index_key.len() as u16).to_le_bytes().to_vec();
+        bytes.extend(index_key);
+        if let Position::At(primary_key) = &self.primary_key {
+            bytes.extend(primary_key.into_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let len = u16::from_le_bytes(bytes[0..2].try_into().unwrap()) as usize;
+        let index_key = IK::from_bytes(&bytes[2..2 + len]);
+        let primary_key = K::from_bytes(&bytes[2 + len..]);
+        IndexKey { index_key, primary_key: Position::At(primary_key) }
+    }
+
+    fn decodes(bytes: &[u8]) -> bool {
+        if bytes.len() < 2 {
+            return false;
+        }
+        let len = u16::from_le_bytes([bytes[0], bytes[1]]) as usize;
+        bytes.len() >= 2 + len && IK::decodes(&bytes[2..2 + len]) && K::decodes(&bytes[2 + len..])
+    }
+}
+
+enum IndexTree<K, IK> {
+    Unique(BTree<IK, K>),
+    NonUnique(BTree<IndexKey<IK, K>, u8>),
+}
+
+pub struct Index<K, V, IK> {
+    tree: IndexTree<K, IK>,
+    extract: Box<dyn Fn(&V) -> IK + Send + Sync>,
+}
+
+impl<K, V, IK> Index<K, V, IK>
+    where K: Ord + SlotBytes + Clone + Debug,
+          IK: Ord + SlotBytes + Clone + Debug,
+{
+    pub fn unique(tree: BTree<IK, K>, extract: impl Fn(&V) -> IK + Send + Sync + 'static) -> Self {
+        Index { tree: IndexTree::Unique(tree), extract: Box::new(extract) }
+    }
+
+    pub fn non_unique(tree: BTree<IndexKey<IK, K>, u8>, extract: impl Fn(&V) -> IK + Send + Sync + 'static) -> Self {
+        Index { tree: IndexTree::NonUnique(tree), extract: Box::new(extract) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match &self.tree {
+            IndexTree::Unique(tree) => tree.root_page_id().is_none(),
+            IndexTree::NonUnique(tree) => tree.root_page_id().is_none(),
+        }
+    }
+
+    pub fn primary_keys(&self, index_key: &IK) -> Vec<K> {
+        match &self.tree {
+            IndexTree::Unique(tree) => tree.search(index_key).into_iter().collect(),
+            IndexTree::NonUnique(tree) => {
+                let start = IndexKey { index_key: index_key.clone(), primary_key: Position::Before };
+                let end = IndexKey { index_key: index_key.clone(), primary_key: Position::After };
+                tree.range((Bound::Excluded(start), Bound::Excluded(end))).into_iter()
+                    .filter_map(|(key, _)| key.primary_key().cloned())
+                    .collect()
+            },
+        }
+    }
+}
+
+// what Indexed needs from an index without knowing its key type
+trait Maintain<K, V>: Send + Sync {
+    fn check(&self, key: &K, value: &V) -> Result<(), Error>;
+    fn insert(&self, key: &K, value: &V) -> Result<(), Error>;
+    fn delete(&self, key: &K, value: &V);
+    fn changes(&self, old: &V, new: &V) -> bool;
+}
+
+impl<K, V, IK> Maintain<K, V> for Index<K, V, IK>
+    where K: Ord + SlotBytes + Clone + Debug + Send + Sync,
+          V: Send + Sync,
+          IK: Ord + SlotBytes + Clone + Debug + Send + Sync,
+{
+    fn check(&self, key: &K, value: &V) -> Result<(), Error> {
+        match &self.tree {
+            IndexTree::Unique(tree) => match tree.search(&(self.extract)(value)) {
+                Ok(other) if other.cmp(key) != Ordering::Equal => Err(Error::UniqueViolation),
+                _ => Ok(()),
+            },
+            IndexTree::NonUnique(_) => Ok(()),
+        }
+    }
+
+    fn insert(&self, key: &K, value: &V) -> Result<(), Error> {
+        let index_key = (self.extract)(value);
+        match &self.tree {
+            IndexTree::Unique(tree) => tree.insert(index_key, key.clone()),
+            IndexTree::NonUnique(tree) => tree.insert(IndexKey::new(index_key, key.clone()), 0),
+        }
+    }
+
+    fn delete(&self, key: &K, value: &V) {
+        let index_key = (self.extract)(value);
+        match &self.tree {
+            IndexTree::Unique(tree) => tree.delete(&index_key),
+            IndexTree::NonUnique(tree) => tree.delete(&IndexKey::new(index_key, key.clone())),
+        }
+    }
+
+    fn changes(&self, old: &V, new: &V) -> bool {
+        (self.extract)(old) != (self.extract)(new)
+    }
+}
+
+// a primary tree whose writes keep every registered index in sync
+pub struct Indexed<K, V> {
+    // lent out read-only, a write past Indexed would leave the indexes behind
+    primary: ReadOnlyBTree<K, V>,
+    indexes: Vec<Arc<dyn Maintain<K, V>>>,
+    // index checks and the writes they guard happen as one step
+    write_latch: Mutex<()>,
+}
+
+impl<K, V> Indexed<K, V>
+    where K: Ord + SlotBytes + Clone + Debug + Send + Sync + 'static,
+          V: SlotBytes + Clone + Debug + Send + Sync + 'static,
+{
+    pub fn new(primary: BTree<K, V>) -> Self {
+        Indexed { primary: ReadOnlyBTree::new(primary), indexes: vec![], write_latch: Mutex::new(()) }
+    }
+
+    pub fn primary(&self) -> &ReadOnlyBTree<K, V> {
+        &self.primary
+    }
+
+    // an empty index is filled from the primary tree first, a reopened one is taken as it is
+    pub fn add_index<IK>(&mut self, index: Index<K, V, IK>) -> Result<Arc<Index<K, V, IK>>, Error>
+        where IK: Ord + SlotBytes + Clone + Debug + Send + Sync + 'static,
+    {
+        if index.is_empty() {
+            for (key, value) in self.primary.range(..) {
+                index.check(&key, &value)?;
+                index.insert(&key, &value)?;
+            }
+        }
+        let index = Arc::new(index);
+        self.indexes.push(index.clone());
+        Ok(index)
+    }
+
+    pub fn search(&self, key: &K) -> Result<V, Error> {
+        self.primary.search(key)
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Result<(), Error> {
+        let _latch = self.write_latch.lock().unwrap();
+        match self.primary.search(&key) {
+            Ok(old) => return self.replace(&key, old, value),
+            Err(Error::NotFound) | Err(Error::NoPage) => {},
+            Err(error) => return Err(error),
+        }
+        for index in &self.indexes {
+            index.check(&key, &value)?;
+        }
+        // the primary first, as update and delete write it
+        self.primary.btree.insert(key.clone(), value.clone())?;
+        for index in &self.indexes {
+            index.insert(&key, &value)?;
+        }
+        Ok(())
+    }
+
+    pub fn update(&self, key: &K, value: V) -> Result<(), Error> {
+        let _latch = self.write_latch.lock().unwrap();
+        let old = self.primary.search(key)?;
+        self.replace(key, old, value)
+    }
+
+    pub fn delete(&self, key: &K) -> Result<(), Error> {
+        let _latch = self.write_latch.lock().unwrap();
+        let old = self.primary.search(key)?;
+        self.primary.btree.delete(key);
+        for index in &self.indexes {
+            index.delete(key, &old);
+        }
+        Ok(())
+    }
+
+    pub fn lookup_by_index<IK>(&self, index: &Index<K, V, IK>, index_key: &IK) -> Result<(K, V), Error>
+        where IK: Ord + SlotBytes + Clone + Debug,
+    {
+        self.lookup_all_by_index(index, index_key).into_iter().next().ok_or(Error::NotFound)
+    }
+
+    pub fn lookup_all_by_index<IK>(&self, index: &Index<K, V, IK>, index_key: &IK) -> Vec<(K, V)>
+        where IK: Ord + SlotBytes + Clone + Debug,
+    {
+        index.primary_keys(index_key).into_iter()
+            .filter_map(|key| self.primary.search(&key).ok().map(|value| (key, value)))
+            .collect()
+    }
+
+    fn replace(&self, key: &K, old: V, value: V) -> Result<(), Error> {
+        let changed = self.indexes.iter()
+            .filter(|index| index.changes(&old, &value))
+            .collect::<Vec<_>>();
+        for index in &changed {
+            index.check(key, &value)?;
+        }
+        self.primary.btree.update(key, value.clone())?;
+        for index in changed {
+            index.delete(key, &old);
+            index.insert(key, &value)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use std::fs::remove_file;
+
+use crate::db::Db;
+use crate::error::Error;
+use crate::index::Index;
+use crate::index::IndexKey;
+use crate::index::Indexed;
+
+
+// email and city separated by a comma
+fn email(user: &str) -> String {
+    user.split(',').next().unwrap().to_string()
+}
+
+fn city(user: &str) -> String {
+    user.split(',').nth(1).unwrap().to_string()
+}
+
+#[test]
+fn test_unique_index() {
+    let p = "test_unique_index";
+    let db = Db::open(p).unwrap();
+    let mut users = Indexed::new(db.create_tree::<u16, String>("users").unwrap());
+    let by_email = users.add_index(Index::unique(db.create_tree::<String, u16>("users.email").unwrap(), |user: &String| email(user))).unwrap();
+
+    assert_eq!(users.insert(1, "a@x,tokyo".to_string()), Ok(()));
+    assert_eq!(users.insert(2, "b@x,osaka".to_string()), Ok(()));
+    assert_eq!(users.insert(3, "a@x,kyoto".to_string()), Err(Error::UniqueViolation));
+    assert_eq!(users.update(&2, "c@x,osaka".to_string()), Ok(()));
+    assert_eq!(users.insert(1, "a@x,nagoya".to_string()), Ok(()));
+
+    let found = users.lookup_by_index(&by_email, &"c@x".to_string());
+    let old_email = users.lookup_by_index(&by_email, &"b@x".to_string());
+    let moved = users.lookup_by_index(&by_email, &"a@x".to_string());
+    assert_eq!(users.delete(&1), Ok(()));
+    let deleted = users.lookup_by_index(&by_email, &"a@x".to_string());
+    let primary = users.primary().range(..);
+    let _ = remove_file(p);
+    assert_eq!(found, Ok((2, "c@x,osaka".to_string())));
+    assert_eq!(old_email, Err(Error::NotFound));
+    assert_eq!(moved, Ok((1, "a@x,nagoya".to_string())));
+    assert_eq!(deleted, Err(Error::NotFound));
+    assert_eq!(users.search(&3), Err(Error::NotFound));
+    assert_eq!(primary, [(2, "c@x,osaka".to_string())]);
+}
+
+#[test]
+fn test_non_unique_index() {
+    let p = "test_non_unique_index";
+    let db = Db::open(p).unwrap();
+    let mut users = Indexed::new(db.create_tree::<u16, String>("users").unwrap());
+    for (key, user) in [(1, "a@x,tokyo"), (2, "b@x,osaka"), (3, "c@x,tokyo")] {
+        let _ = users.insert(key, user.to_string());
+    }
+    // filled from the users already there
+    let tree = db.create_tree::<IndexKey<String, u16>, u8>("users.city").unwrap();
+    let by_city = users.add_index(Index::non_unique(tree, |user: &String| city(user))).unwrap();
+    let _ = users.insert(4, "d@x,tokyo".to_string());
+    let _ = users.delete(&1);
+    let _ = users.update(&2, "b@x,tokyo".to_string());
+
+    let tokyo = users.lookup_all_by_index(&by_city, &"tokyo".to_string()).into_iter()
+        .map(|(key, _)| key)
+        .collect::<Vec<_>>();
+    let osaka = users.lookup_all_by_index(&by_city, &"osaka".to_string());
+    let _ = remove_file(p);
+    assert_eq!(tokyo, [2, 3, 4]);
+    assert_eq!(osaka, []);
+}
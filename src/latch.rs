@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use std::sync::Condvar;
+use std::sync::Mutex;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LatchMode { Shared, Exclusive }
+
+#[derive(Debug, Default)]
+struct PageLatch {
+    readers: usize,
+    writer: bool,
+}
+
+// only pages that are latched right now have an entry
+#[derive(Debug, Default)]
+pub struct Latches {
+    pages: Mutex<HashMap<u16, PageLatch>>,
+    released: Condvar,
+}
+
+impl Latches {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn lock(&self, page_id: u16, mode: LatchMode) -> Latch<'_> {
+        let mut pages = self.pages.lock().unwrap();
+        loop {
+            let latch = pages.entry(page_id).or_default();
+            match mode {
+                LatchMode::Shared if !latch.writer => {
+                    latch.readers += 1;
+                    break;
+                },
+                LatchMode::Exclusive if !latch.writer && latch.readers == 0 => {
+                    latch.writer = true;
+                    break;
+                },
+                _ => pages = self.released.wait(pages).unwrap(),
+            }
+        }
+        Latch { latches: self, page_id, mode }
+    }
+
+    fn unlock(&self, page_id: u16, mode: LatchMode) {
+        let mut pages = self.pages.lock().unwrap();
+        let latch = pages.get_mut(&page_id).unwrap();
+        match mode {
+            LatchMode::Shared => latch.readers -= 1,
+            LatchMode::Exclusive => latch.writer = false,
+        }
+        if latch.readers == 0 && !latch.writer {
+            pages.remove(&page_id);
+        }
+        self.released.notify_all();
+    }
+}
+
+#[derive(Debug)]
+pub struct Latch<'a> {
+    latches: &'a Latches,
+    pub page_id: u16,
+    mode: LatchMode,
+}
+
+impl Drop for Latch<'_> {
+    fn drop(&mut self) {
+        self.latches.unlock(self.page_id, self.mode);
+    }
+}
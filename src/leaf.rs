@@ -8,22 +8,22 @@ use crate::slot::SlotBytes;
 use crate::slotted::pointer::LeafPointer;
 
 
-pub struct Leaf<K: Ord + SlotBytes + Debug, V: SlotBytes + Debug> {
+pub struct Leaf<K: SlotBytes + Debug, V: SlotBytes + Debug> {
     pub slotted: Slotted<K, V, LeafPointer>
 }
 
-impl<K: Ord + SlotBytes + Debug, V: SlotBytes + Debug> Leaf<K, V> {
+impl<K: SlotBytes + Debug, V: SlotBytes + Debug> Leaf<K, V> {
     pub fn new(mut slotted: Slotted<K, V, LeafPointer>) -> Self {
         slotted.set_node_type(NodeType::Leaf);
-        Leaf { slotted: slotted }
+        Leaf { slotted }
     }
 }
 
-impl<K: Ord + SlotBytes + Debug, V: SlotBytes + Debug> Debug for Leaf<K, V> {
+impl<K: SlotBytes + Debug, V: SlotBytes + Debug> Debug for Leaf<K, V> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         let _  = write!(f, "({:?}): ", self.slotted.page.id);
         f.debug_list()
-            .entries(&self.slotted.slots())
+            .entries(self.slotted.slots())
             .finish()
     }
 }
@@ -31,9 +31,9 @@ impl<K: Ord + SlotBytes + Debug, V: SlotBytes + Debug> Debug for Leaf<K, V> {
 // #[repr(C)]
 // #[derive(Debug, Clone, Copy)]
 // struct Header {
-//     number_of_pointer: u16,
+//     number_of_pointer: u16, // top bits: branch, prefix compressed, varint pointers
 //     end_of_free_space: u16,
 //     _padding2: u16,
-//     _padding3: u16,
+//     fragmented_bytes: u16,
 // }
 
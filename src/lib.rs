@@ -3,12 +3,34 @@ mod error;
 mod slotted;
 mod slot;
 mod branch;
+mod cipher;
+#[cfg(feature = "compression")]
+mod codec;
+mod comparator;
 mod leaf;
 mod node;
+mod latch;
 mod meta;
+mod options;
+mod text;
 
 mod storage;
+mod store;
 mod btree;
+mod db;
+mod index;
 
 
-pub use btree::*;
\ No newline at end of file
+pub use btree::*;
+#[cfg(feature = "compression")]
+pub use codec::*;
+pub use comparator::*;
+pub use db::*;
+pub use error::Error;
+pub use index::*;
+pub use options::*;
+pub use page::PAGE_SIZE;
+pub use slot::SlotBytes;
+pub use slotted::PageDamage;
+pub use store::*;
+pub use text::*;
\ No newline at end of file
@@ -3,12 +3,23 @@ use std::fmt::Error;
 use std::fmt::Formatter;
 
 use crate::page::Page;
+use crate::slotted::pointer::PointerFormat;
+
+const PREFIX_COMPRESSION: u16 = 0x0001;
+const VARINT_POINTERS: u16 = 0x0002;
+const COMPARATOR_NAME_OFFSET: usize = 4;
+const COMPARATOR_NAME_MAX_LEN: usize = 32;
+const MAX_KEY_SIZE_OFFSET: usize = 38;
+const FREE_LIST_HEAD_OFFSET: usize = 40;
+const JOURNAL_START_OFFSET: usize = 42;
+const JOURNAL_PAGES_OFFSET: usize = 44;
+const COMMITTED_LEN_OFFSET: usize = 46;
 
 pub struct Meta { pub page: Page }
 
 impl Meta {
     pub fn new(page: Page) -> Self {
-        Meta { page: page }
+        Meta { page }
     }
 
     pub fn root_page_id(&self) -> u16 {
@@ -18,10 +29,92 @@ impl Meta {
     pub fn set_root_page_id(&mut self, root_page_id: u16) {
         self.page.set_u16_bytes(0, root_page_id);
     }
+
+    pub fn prefix_compression(&self) -> bool {
+        self.page.u16_bytes(2) & PREFIX_COMPRESSION != 0
+    }
+
+    pub fn set_prefix_compression(&mut self, prefix_compression: bool) {
+        let flags = self.page.u16_bytes(2);
+        if prefix_compression {
+            self.page.set_u16_bytes(2, flags | PREFIX_COMPRESSION);
+        } else {
+            self.page.set_u16_bytes(2, flags & !PREFIX_COMPRESSION);
+        }
+    }
+
+    pub fn pointer_format(&self) -> PointerFormat {
+        if self.page.u16_bytes(2) & VARINT_POINTERS != 0 {
+            PointerFormat::Varint
+        } else {
+            PointerFormat::Fixed
+        }
+    }
+
+    pub fn set_pointer_format(&mut self, format: PointerFormat) {
+        let flags = self.page.u16_bytes(2);
+        match format {
+            PointerFormat::Fixed => self.page.set_u16_bytes(2, flags & !VARINT_POINTERS),
+            PointerFormat::Varint => self.page.set_u16_bytes(2, flags | VARINT_POINTERS),
+        }
+    }
+}
+
+impl Meta {
+    pub fn comparator_name(&self) -> String {
+        let len = self.page.bytes[COMPARATOR_NAME_OFFSET] as usize;
+        let start = COMPARATOR_NAME_OFFSET + 1;
+        String::from_utf8_lossy(&self.page.bytes[start..start + len]).to_string()
+    }
+
+    pub fn set_comparator_name(&mut self, name: &str) {
+        let bytes = name.as_bytes();
+        assert!(bytes.len() <= COMPARATOR_NAME_MAX_LEN, "comparator name is too long: {}", name);
+        self.page.bytes[COMPARATOR_NAME_OFFSET] = bytes.len() as u8;
+        self.page.set_bytes(COMPARATOR_NAME_OFFSET + 1, bytes.to_vec());
+    }
+
+    // the longest key ever inserted, an upper bound for separators in branches
+    pub fn max_key_size(&self) -> u16 {
+        self.page.u16_bytes(MAX_KEY_SIZE_OFFSET)
+    }
+
+    pub fn set_max_key_size(&mut self, size: u16) {
+        self.page.set_u16_bytes(MAX_KEY_SIZE_OFFSET, size);
+    }
+
+    // only used on page 0
+    pub fn free_list_head(&self) -> u16 {
+        self.page.u16_bytes(FREE_LIST_HEAD_OFFSET)
+    }
+
+    pub fn set_free_list_head(&mut self, page_id: u16) {
+        self.page.set_u16_bytes(FREE_LIST_HEAD_OFFSET, page_id);
+    }
+
+    // only used on page 0: where a flush put the pages it commits, 0 once they are in place
+    pub fn journal_start(&self) -> u16 {
+        self.page.u16_bytes(JOURNAL_START_OFFSET)
+    }
+
+    pub fn journal_pages(&self) -> u16 {
+        self.page.u16_bytes(JOURNAL_PAGES_OFFSET)
+    }
+
+    // only used on page 0: pages in the file as of the last flush, 0 when it is not known
+    pub fn committed_len(&self) -> u16 {
+        self.page.u16_bytes(COMMITTED_LEN_OFFSET)
+    }
+
+    pub fn set_journal(&mut self, start: u16, pages: u16, committed_len: u16) {
+        self.page.set_u16_bytes(JOURNAL_START_OFFSET, start);
+        self.page.set_u16_bytes(JOURNAL_PAGES_OFFSET, pages);
+        self.page.set_u16_bytes(COMMITTED_LEN_OFFSET, committed_len);
+    }
 }
 
 impl Debug for Meta {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        write!(f, "root_page_id={:?} ", self.root_page_id())
+        write!(f, "root_page_id={:?} prefix_compression={:?} pointer_format={:?} comparator={:?} ", self.root_page_id(), self.prefix_compression(), self.pointer_format(), self.comparator_name())
     }
 }
@@ -3,6 +3,7 @@ use std::fmt::Debug;
 // use std::fmt::Formatter;
 
 use crate::branch::Branch;
+use crate::comparator::Compare;
 use crate::leaf::Leaf;
 use crate::page::Page;
 use crate::slot::SlotBytes;
@@ -11,21 +12,21 @@ use crate::slotted::pointer::BranchPointer;
 use crate::slotted::pointer::LeafPointer;
 
 #[derive(Debug)]
-pub enum Node<K: Ord + SlotBytes + Debug, V: SlotBytes + Debug> {
+pub enum Node<K: SlotBytes + Debug, V: SlotBytes + Debug> {
     Leaf(Leaf<K, V>),
     Branch(Branch<K>),
 }
 
-impl<K: Ord + SlotBytes + Debug, V: SlotBytes + Debug> Node<K, V> {
-    pub fn new(page: Page) -> Self {
+impl<K: SlotBytes + Debug, V: SlotBytes + Debug> Node<K, V> {
+    pub fn new(page: Page, compare: Compare<K>) -> Self {
         match NodeType::new(&page) {
             NodeType::Leaf => {
-                let slotted = Slotted::<K, V, LeafPointer>::new(page);
+                let slotted = Slotted::<K, V, LeafPointer>::new(page, compare);
                 let leaf = Leaf::new(slotted);                
                 Node::Leaf(leaf)
             },
             NodeType::Branch => {
-                let slotted = Slotted::<K, u16, BranchPointer>::new(page);
+                let slotted = Slotted::<K, u16, BranchPointer>::new(page, compare);
                 let branch = Branch::new(slotted);
                 Node::Branch(branch)
             }
@@ -61,7 +62,7 @@ impl NodeType {
     }
 }
 
-// impl<K: Ord + SlotBytes + Debug, V: Debug> Node<K, V> {
+// impl<K: SlotBytes + Debug, V: Debug> Node<K, V> {
 //     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
 //         match self {
 //             Node::Leaf(leaf) => writeln!(f, "{:?}", leaf),
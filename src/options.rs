@@ -0,0 +1,20 @@
+pub use crate::slotted::pointer::PointerFormat;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    pub prefix_compression: bool,
+    pub pointer_format: PointerFormat,
+    // applies to the whole file, trees of a Db take the one it was opened with
+    pub durability: Durability,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    // sync after every page write
+    EveryWrite,
+    // pages wait in memory for flush, which commits them through a journal; a crash leaves the last flush
+    OnFlush,
+    // left to the os, flush does nothing
+    #[default]
+    Never,
+}
@@ -13,7 +13,7 @@ impl<K, V> Slot<K, V> where
     V: SlotBytes + Clone,
 {
     pub fn new(key: K, value: V) -> Self {
-        Slot { key: key, value: value }
+        Slot { key, value }
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
@@ -34,18 +34,38 @@ impl<K, V> Slot<K, V> where
 
 
 pub trait SlotBytes {
+    #[allow(clippy::wrong_self_convention)]
     fn into_bytes(&self) -> Vec<u8>;
     fn from_bytes(bytes: &[u8]) -> Self;
+
+    // whether from_bytes reads the bytes without panicking, check asks before decoding a page
+    fn decodes(_bytes: &[u8]) -> bool where Self: Sized {
+        true
+    }
+
+    // shortest key s with lower < s <= upper, used as separator in branches
+    fn separator(_lower: &Self, upper: &Self) -> Self where Self: Sized + Clone {
+        upper.clone()
+    }
+
+    // milliseconds since the unix epoch after which searches and scans skip the value
+    fn expires_at(&self) -> Option<u64> {
+        None
+    }
 }
 
 impl SlotBytes for u8 {
     fn into_bytes(&self) -> Vec<u8> {
-        vec![self.clone()]
+        vec![*self]
     }
 
     fn from_bytes(bytes: &[u8]) -> Self {
         bytes[0]
     }
+
+    fn decodes(bytes: &[u8]) -> bool {
+        bytes.len() == 1
+    }
 }
 
 impl SlotBytes for u16 {
@@ -60,6 +80,10 @@ impl SlotBytes for u16 {
             panic!("SlotBytes for u16 from_bytes bytes: {:?}", bytes);
         }
     }
+
+    fn decodes(bytes: &[u8]) -> bool {
+        bytes.len() == 2
+    }
 }
 
 impl SlotBytes for u32 {
@@ -67,8 +91,26 @@ impl SlotBytes for u32 {
         self.to_le_bytes().to_vec()
     }
 
-    fn from_bytes(_bytes: &[u8]) -> Self {
-        unimplemented!()
+    fn from_bytes(bytes: &[u8]) -> Self {
+        if let Ok(bytes) = bytes.try_into() {
+            u32::from_le_bytes(bytes)
+        } else {
+            panic!("SlotBytes for u32 from_bytes bytes: {:?}", bytes);
+        }
+    }
+
+    fn decodes(bytes: &[u8]) -> bool {
+        bytes.len() == 4
+    }
+}
+
+impl SlotBytes for Vec<u8> {
+    fn into_bytes(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        bytes.to_vec()
     }
 }
 
@@ -77,8 +119,23 @@ impl SlotBytes for String {
         self.bytes().collect::<Vec<_>>()
     }
 
-    fn from_bytes<'a>(bytes: &'a [u8]) -> Self {
+    fn from_bytes(bytes: &[u8]) -> Self {
         String::from_utf8(bytes.to_vec()).unwrap()
     }
+
+    fn decodes(bytes: &[u8]) -> bool {
+        std::str::from_utf8(bytes).is_ok()
+    }
+
+    fn separator(lower: &Self, upper: &Self) -> Self {
+        let common_len = lower.bytes().zip(upper.bytes())
+            .take_while(|(l, u)| l == u)
+            .count();
+        let end = upper.char_indices()
+            .map(|(i, c)| i + c.len_utf8())
+            .find(|end| *end > common_len)
+            .unwrap_or(upper.len());
+        upper[..end].to_string()
+    }
 }
 
@@ -4,11 +4,13 @@ mod fmt;
 mod test;
 
 
+use std::cmp::Ordering;
 use std::convert::TryInto;
 use std::fmt::Debug;
 use std::marker::PhantomData;
 use std::ops::Range;
 
+use crate::comparator::Compare;
 use crate::error::Error;
 use crate::node::NodeType;
 use crate::page::PAGE_SIZE;
@@ -16,34 +18,58 @@ use crate::page::Page;
 use crate::slot::Slot;
 use crate::slot::SlotBytes;
 use crate::slotted::pointer::Pointer;
+use crate::slotted::pointer::PointerFormat;
 
 
 const HEADER_LEN: usize = 8;
+const BRANCH_FLAG: u16 = 0x8000;
+const PREFIX_FLAG: u16 = 0x4000;
+const VARINT_FLAG: u16 = 0x2000;
+const NUMBER_OF_POINTER_MASK: u16 = 0x1FFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageDamage {
+    FreeSpaceOutOfPage,
+    // the pointer array runs past end_of_free_space
+    PointersOverrunSlots,
+    SlotOutOfPage(usize),
+    SlotsOverlap(usize),
+    // live slots and fragmented bytes don't add up to the slot area
+    FragmentedBytes,
+    // the key or value of the slot is not something its type reads
+    Undecodable(usize),
+}
 
-pub struct Slotted<K: Ord + SlotBytes + Debug, V: SlotBytes + Debug, P: Pointer + Debug> {
+pub struct Slotted<K: SlotBytes + Debug, V: SlotBytes + Debug, P: Pointer + Debug> {
     pub page: Page,
+    compare: Compare<K>,
     _phantom_key: PhantomData<fn() -> K>,
     _phantom_value: PhantomData<fn() -> V>,
     _phantom_pointer: PhantomData<fn() -> P>,
 }
 
-impl<K: Ord + SlotBytes + Debug, V: SlotBytes + Debug, P: Pointer+ Debug> Slotted<K, V, P> {
-    pub fn new(page: Page) -> Self {
+impl<K: SlotBytes + Debug, V: SlotBytes + Debug, P: Pointer+ Debug> Slotted<K, V, P> {
+    pub fn new(page: Page, compare: Compare<K>) -> Self {
         Slotted::<K, V, P> {
-            page: page, 
+            page, 
+            compare,
             _phantom_key: PhantomData,
             _phantom_value: PhantomData,
             _phantom_pointer: PhantomData,
         }
     }
 
-    pub fn create(page: Page) -> Self {
-        let mut slotted = Slotted::new(page);
+    pub fn create(page: Page, compare: Compare<K>) -> Self {
+        let mut slotted = Slotted::new(page, compare);
         slotted.set_number_of_pointer(0);
         slotted.set_end_of_free_space(PAGE_SIZE as u16);
         slotted
     }
 
+    pub fn compare(&self) -> Compare<K> {
+        self.compare
+    }
+
     pub fn insert(&mut self, slot: &Slot<K, V>) -> Result<(), Error> where
         K: SlotBytes + Clone,
         V: SlotBytes + Clone,
@@ -51,10 +77,16 @@ impl<K: Ord + SlotBytes + Debug, V: SlotBytes + Debug, P: Pointer+ Debug> Slotte
         if self.is_full(slot) {
             return Err(Error::FullLeaf)
         } 
+        let prefix = self.prefix();
+        let key_bytes = slot.key.into_bytes();
+        if !key_bytes.starts_with(&prefix) {
+            let slots = self.raw_slots();
+            self.rebuild(common_prefix(&prefix, &key_bytes), slots);
+        }
         // println!("insert 0: {:?}", &self);
-        self.add_slot(slot);
+        let pointer = self.add_slot(slot);
         // println!("insert 1: {:?}", &self);
-        self.insert_pointer(&slot);
+        self.insert_pointer(slot, pointer);
         // println!("insert 2: {:?}", &self);
         self.increment_number_of_pointer();
         // println!("insert 3: {:?}", &self);
@@ -72,156 +104,282 @@ impl<K: Ord + SlotBytes + Debug, V: SlotBytes + Debug, P: Pointer+ Debug> Slotte
     }
 
     pub fn delete(&mut self, key: &K) -> Result<(), Error> {
-        // println!("delete key: {:?}", key);
         match self.search_pointer(key) {
             Some(pointer_index) => {
                 let pointer = self.pointer_index_to_pointer(pointer_index);
 
                 self.delete_slot(&pointer);
 
-                // end_of_free_space
-                let end_of_free_space = self.end_of_free_space();
-                self.set_end_of_free_space(end_of_free_space + pointer.slot_size());
-
                 self.delete_pointer(pointer_index);
 
                 self.decrement_number_of_pointer();
-                
-                self.update_slot_offsets(pointer);
-                // println!("after delete: {:?}", self);
                 Ok(())
             },
             None => Err(Error::NotFound),
         }
     }
 
+    pub fn update(&mut self, key: &K, value: &V) -> Result<(), Error> where
+        K: SlotBytes + Clone,
+        V: SlotBytes + Clone,
+    {
+        let pointer_index = self.search_pointer(key).ok_or(Error::NotFound)?;
+        let pointer = self.pointer_index_to_pointer(pointer_index);
+        let value_bytes = value.into_bytes();
+
+        if value_bytes.len() == pointer.value_size() as usize {
+            let offset = pointer.value_range().start;
+            self.page.set_bytes(offset, value_bytes);
+            return Ok(())
+        }
+
+        let slot = Slot::new(key.clone(), value.clone());
+        let (_, bytes) = self.slot_bytes(&slot);
+        if (self.end_of_free_space() as usize) < self.start_of_free_space() + bytes.len() {
+            return Err(Error::FullLeaf)
+        }
+        let new_pointer = self.add_slot(&slot);
+        let pointer_offset = self.pointer_offset(pointer_index);
+        self.page.set_bytes(pointer_offset, new_pointer.to_bytes());
+
+        self.delete_slot(&pointer);
+        Ok(())
+    }
+
+    pub fn set_slots(&mut self, slots: Vec<(K, V)>) {
+        let slots = slots.iter()
+            .map(|(key, value)| (key.into_bytes(), value.into_bytes()))
+            .collect::<Vec<_>>();
+        let prefix = self.longest_prefix(&slots);
+        self.rebuild(prefix, slots);
+    }
+
+    pub fn compact(&mut self) {
+        let slots = self.raw_slots();
+        let prefix = self.longest_prefix(&slots);
+        self.rebuild(prefix, slots);
+    }
+
+    pub fn free_space(&self) -> usize {
+        self.end_of_free_space() as usize - self.start_of_free_space()
+    }
+
+    // the first broken invariant of the header and pointer array, then the first slot that would not decode
+    pub fn damage(&self) -> Option<PageDamage> {
+        let end_of_free_space = self.end_of_free_space() as usize;
+        if self.header_len() > PAGE_SIZE || end_of_free_space > PAGE_SIZE {
+            return Some(PageDamage::FreeSpaceOutOfPage);
+        }
+        if self.start_of_free_space() > end_of_free_space {
+            return Some(PageDamage::PointersOverrunSlots);
+        }
+        let mut used = [false; PAGE_SIZE];
+        let mut live_bytes = 0;
+        for index in 0..self.number_of_pointer() as usize {
+            let slot_offset = self.page.u16_bytes(self.pointer_offset(index)) as usize;
+            if slot_offset < end_of_free_space || slot_offset > PAGE_SIZE {
+                return Some(PageDamage::SlotOutOfPage(index));
+            }
+            let slot = range(slot_offset, self.pointer_index_to_pointer(index).slot_size() as usize);
+            if slot.end > PAGE_SIZE {
+                return Some(PageDamage::SlotOutOfPage(index));
+            }
+            if used[slot.clone()].contains(&true) {
+                return Some(PageDamage::SlotsOverlap(index));
+            }
+            live_bytes += slot.len();
+            used[slot].fill(true);
+        }
+        if live_bytes + self.fragmented_bytes() as usize != PAGE_SIZE - end_of_free_space {
+            return Some(PageDamage::FragmentedBytes);
+        }
+        self.pointers().iter()
+            .position(|pointer| !K::decodes(&self.key_bytes(pointer)) || !V::decodes(&self.page.bytes[pointer.value_range()]))
+            .map(PageDamage::Undecodable)
+    }
+
+    pub fn fragmented_bytes(&self) -> u16 {
+        self.page.u16_bytes(6)
+    }
+
     pub fn set_node_type(&mut self, node_type: NodeType) {
         let current = self.page.u16_bytes(0);
         match node_type {
-            NodeType::Leaf => self.page.set_u16_bytes(0, current & 0x7FFF),
-            NodeType::Branch => self.page.set_u16_bytes(0, current | 0x8000),
+            NodeType::Leaf => self.page.set_u16_bytes(0, current & !BRANCH_FLAG),
+            NodeType::Branch => self.page.set_u16_bytes(0, current | BRANCH_FLAG),
+        }
+    }
+
+    pub fn set_prefix_compression(&mut self, prefix_compression: bool) {
+        let slots = self.raw_slots();
+        let current = self.page.u16_bytes(0);
+        if prefix_compression {
+            self.page.set_u16_bytes(0, current | PREFIX_FLAG);
+        } else {
+            self.page.set_u16_bytes(0, current & !PREFIX_FLAG);
+        }
+        self.page.bytes[HEADER_LEN] = 0;
+        self.rebuild(vec![], slots);
+    }
+
+    pub fn is_prefix_compressed(&self) -> bool {
+        self.page.u16_bytes(0) & PREFIX_FLAG != 0
+    }
+
+    pub fn set_pointer_format(&mut self, format: PointerFormat) {
+        let slots = self.raw_slots();
+        let prefix = self.prefix();
+        let current = self.page.u16_bytes(0);
+        match format {
+            PointerFormat::Fixed => self.page.set_u16_bytes(0, current & !VARINT_FLAG),
+            PointerFormat::Varint => self.page.set_u16_bytes(0, current | VARINT_FLAG),
+        }
+        self.rebuild(prefix, slots);
+    }
+
+    pub fn pointer_format(&self) -> PointerFormat {
+        if self.page.u16_bytes(0) & VARINT_FLAG != 0 {
+            PointerFormat::Varint
+        } else {
+            PointerFormat::Fixed
+        }
+    }
+
+    pub fn prefix(&self) -> Vec<u8> {
+        if self.is_prefix_compressed() {
+            let len = self.page.bytes[HEADER_LEN] as usize;
+            self.page.bytes[range(HEADER_LEN + 1, len)].to_vec()
+        } else {
+            vec![]
         }
     }
 
     pub fn pointers(&self) -> Vec<P> {
-        self.page.bytes[self.pointers_range()].chunks(P::len())
-            .map(|chunk| Self::offset_to_pointer(chunk, 0))
+        (0..self.number_of_pointer() as usize)
+            .map(|index| self.pointer_index_to_pointer(index))
             .collect::<Vec<_>>()
     }
 
-    pub fn keys<'a>(&self) -> Vec<K>
+    pub fn keys(&self) -> Vec<K>
         where K: SlotBytes
     {
-        let range = self.pointers_range();
-        self.page.bytes[range].chunks(Self::pointer_size())
-            .map(|chunk| Self::offset_to_pointer(chunk, 0))
-            .map(|pointer| {
-                let bytes = &self.page.bytes[pointer.key_range()];
-                K::from_bytes(bytes)
-            })
+        self.pointers().iter()
+            .map(|pointer| K::from_bytes(&self.key_bytes(pointer)))
             .collect::<Vec<_>>()
     }
 
     pub fn slots(&self) -> Vec<(K, V)> {
-        let pointers = self.page.bytes[self.pointers_range()].chunks(P::len())
-            .map(|chunk| Self::offset_to_pointer(chunk, 0))
-            .collect::<Vec<P>>();
-
-        pointers.iter().map(|pointer| {
-            let key = K::from_bytes(&self.page.bytes[pointer.key_range()]);
+        self.pointers().iter().map(|pointer| {
+            let key = K::from_bytes(&self.key_bytes(pointer));
             let value = V::from_bytes(&self.page.bytes[pointer.value_range()]);
             (key, value)
         }).collect::<Vec<_>>()
     }
 
-    fn is_full(&self, slot: &Slot<K, V>) -> bool where
+    pub fn is_full(&self, slot: &Slot<K, V>) -> bool where
         K: SlotBytes + Clone,
         V: SlotBytes + Clone,
     {
-        let offset_pointer = self.start_of_free_space();
-
-        let bytes = slot.to_bytes();
-        let end_of_free_space = self.end_of_free_space() as usize;
-        if end_of_free_space < bytes.len() {
-            return true;
-        }
-        // println!("end_of_free_space: {:?} bytes.len: {:?} Self::pointer_size: {:?}", end_of_free_space, bytes.len(), Self::pointer_size());
-        if end_of_free_space < bytes.len() + Self::pointer_size() {
-            return true;
+        let prefix = self.prefix();
+        let key_bytes = slot.key.into_bytes();
+        let value_size = slot.value_size();
+        if key_bytes.starts_with(&prefix) {
+            let key_size = (key_bytes.len() - prefix.len()) as u16;
+            let slot_len = self.new_pointer(0, key_size, value_size).slot_size() as usize;
+            let end_of_free_space = self.end_of_free_space() as usize;
+            end_of_free_space < self.start_of_free_space() + slot_len + self.pointer_size()
+        } else {
+            // the page has to be rebuilt with a shorter prefix first
+            let prefix_len = common_prefix(&prefix, &key_bytes).len();
+            self.rebuilt_len(prefix_len, key_bytes.len(), value_size as usize) > PAGE_SIZE
         }
-        let offset_slot = end_of_free_space - bytes.len() - Self::pointer_size();
+    }
+
+    // true if one more slot fits even when the page has to be rebuilt without any prefix
+    pub fn has_room_for(&self, key_size: usize, value_size: usize) -> bool {
+        self.rebuilt_len(0, key_size, value_size) <= PAGE_SIZE
+    }
 
-        offset_slot < offset_pointer
+    // the bytes set_slots would fill, header and shared prefix included
+    pub fn built_len(&self, slots: &[(K, V)]) -> usize {
+        let slots = slots.iter()
+            .map(|(key, value)| (key.into_bytes(), value.into_bytes()))
+            .collect::<Vec<_>>();
+        let prefix_len = self.longest_prefix(&slots).len();
+        let header_len = if self.is_prefix_compressed() { HEADER_LEN + 1 + prefix_len } else { HEADER_LEN };
+        let slots_len = slots.iter()
+            .map(|(key, value)| self.new_pointer(0, (key.len() - prefix_len) as u16, value.len() as u16).slot_size() as usize)
+            .sum::<usize>();
+        header_len + self.pointer_size() * slots.len() + slots_len
+    }
+
+    fn rebuilt_len(&self, prefix_len: usize, key_size: usize, value_size: usize) -> usize {
+        let mut sizes = self.raw_slots().iter()
+            .map(|(key, value)| (key.len() - prefix_len, value.len()))
+            .collect::<Vec<_>>();
+        sizes.push((key_size - prefix_len, value_size));
+        let slots_len = sizes.iter()
+            .map(|(key_size, value_size)| self.new_pointer(0, *key_size as u16, *value_size as u16).slot_size() as usize)
+            .sum::<usize>();
+        let header_len = HEADER_LEN + 1 + prefix_len;
+        let pointers_len = self.pointer_size() * sizes.len();
+        header_len + pointers_len + slots_len
     }
 
-    fn add_slot(&mut self, slot: &Slot<K, V>)
+    fn add_slot(&mut self, slot: &Slot<K, V>) -> P
         where 
             K: SlotBytes + Clone,
             V: SlotBytes + Clone,
     {
-        let bytes = slot.to_bytes();
+        let (key_size, bytes) = self.slot_bytes(slot);
         let end_of_free_space = self.end_of_free_space() as usize;
         let offset = end_of_free_space - bytes.len();
         self.page.set_bytes(offset, bytes);
         self.set_end_of_free_space(offset.try_into().unwrap());
+        self.new_pointer(offset as u16, key_size, slot.value_size())
     }
 
-    fn insert_pointer(&mut self, slot: &Slot<K, V>)
+    fn insert_pointer(&mut self, slot: &Slot<K, V>, pointer: P)
         where K: SlotBytes + Clone,
               V: SlotBytes + Clone,
     {
         let keys = self.keys();
         let insertion_point = 
             if let Some(p) = keys.iter()
-                .position(|k| &slot.key < k ) {
+                .position(|k| (self.compare)(&slot.key, k) == Ordering::Less) {
                 p
             } else {
                 keys.len()
             };
-        let start_offset = Self::pointer_offset(insertion_point);
+        let start_offset = self.pointer_offset(insertion_point);
         let end_offset = self.start_of_free_space();
-        self.page.bytes.copy_within(start_offset..end_offset, start_offset + Self::pointer_size());
-
-        let pointer = P::new(self.end_of_free_space(), slot.key_size(), slot.value_size());
-        self.page.set_bytes(start_offset.into(), pointer.to_bytes());
+        let pointer_size = self.pointer_size();
+        self.page.bytes.copy_within(start_offset..end_offset, start_offset + pointer_size);
+        self.page.set_bytes(start_offset, pointer.to_bytes());
     }
 
     fn delete_slot(&mut self, pointer: &impl Pointer) {
-        let start_of_slots = self.end_of_free_space() as usize;
-        let start_of_deleting_slot = pointer.slot_offset() as usize;
-        let slot_len = pointer.slot_size() as usize;
-        // println!("delete_slot: start_of_slots: {:?} start_of_deleting_slot: {:?}", start_of_slots, start_of_deleting_slot);
-        if start_of_slots < start_of_deleting_slot {
-            let bytes = &mut self.page.bytes;
-            bytes.copy_within(start_of_slots..start_of_deleting_slot, start_of_slots + slot_len);
+        let start_of_slots = self.end_of_free_space();
+        let slot_len = pointer.slot_size();
+        self.page.bytes[range(pointer.slot_offset() as usize, slot_len as usize)].fill(0);
+        if pointer.slot_offset() == start_of_slots {
+            self.set_end_of_free_space(start_of_slots + slot_len);
+        } else {
+            let fragmented_bytes = self.fragmented_bytes();
+            self.set_fragmented_bytes(fragmented_bytes + slot_len);
         }
-        // println!("delete_slot: slot_len: {:?}", slot_len);
-        &self.page.bytes[range(start_of_slots, slot_len)].fill(0);
     }
 
     fn delete_pointer(&mut self, pointer_index: usize) {
-        let start_of_deleting_pointer = Self::pointer_offset(pointer_index);
-        let start_of_pointers = start_of_deleting_pointer + Self::pointer_size();
+        let start_of_deleting_pointer = self.pointer_offset(pointer_index);
+        let start_of_pointers = start_of_deleting_pointer + self.pointer_size();
         let end_of_pointers = self.start_of_free_space();
         let range = start_of_pointers..end_of_pointers;
+        let pointer_size = self.pointer_size();
         let bytes = &mut self.page.bytes;
         // println!("delete_pointer: range:{:?} start_of_deleting_pointer: {:?}", range, start_of_deleting_pointer);
         bytes.copy_within(range, start_of_deleting_pointer);
-        bytes[end_of_pointers - Self::pointer_size()..end_of_pointers].fill(0);
-    }
-
-    fn update_slot_offsets(&mut self, pointer: impl Pointer) {
-        let range = self.pointers_range();
-        let rewriting_indices = self.page.bytes[range].chunks(Self::pointer_size())
-            .map(|chunk| Self::offset_to_pointer(chunk, 0))
-            .enumerate()
-            .filter(|(_, ptr)| ptr.slot_offset() < pointer.slot_offset())
-            .collect::<Vec<_>>();
-
-        for (ptr_index, ptr) in rewriting_indices.iter() {
-            let ptr_offset = Self::pointer_offset(ptr_index.clone());
-            self.page.set_u16_bytes(ptr_offset, ptr.slot_offset() + pointer.slot_size());
-        }
+        bytes[end_of_pointers - pointer_size..end_of_pointers].fill(0);
     }
 
     fn search_slot_offset(&self, key: &K) -> Option<P> {
@@ -229,32 +387,94 @@ impl<K: Ord + SlotBytes + Debug, V: SlotBytes + Debug, P: Pointer+ Debug> Slotte
             self.pointer_index_to_pointer(key_index)
         })
     }
+
+    fn slot_bytes(&self, slot: &Slot<K, V>) -> (u16, Vec<u8>)
+        where K: SlotBytes + Clone,
+              V: SlotBytes + Clone,
+    {
+        let prefix_len = self.prefix().len();
+        let key_size = slot.key_size() - prefix_len as u16;
+        let mut bytes = self.new_pointer(0, key_size, slot.value_size()).slot_header();
+        bytes.append(&mut slot.to_bytes().split_off(prefix_len));
+        (key_size, bytes)
+    }
+
+    fn longest_prefix(&self, slots: &[(Vec<u8>, Vec<u8>)]) -> Vec<u8> {
+        if self.is_prefix_compressed() {
+            slots.iter().map(|(key, _)| key.clone())
+                .reduce(|prefix, key| common_prefix(&prefix, &key))
+                .unwrap_or_default()
+        } else {
+            vec![]
+        }
+    }
+
+    fn raw_slots(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.pointers().iter()
+            .map(|pointer| (self.key_bytes(pointer), self.page.bytes[pointer.value_range()].to_vec()))
+            .collect::<Vec<_>>()
+    }
+
+    fn rebuild(&mut self, prefix: Vec<u8>, slots: Vec<(Vec<u8>, Vec<u8>)>) {
+        let number_of_pointer = slots.len();
+        self.page.bytes[HEADER_LEN..PAGE_SIZE].fill(0);
+
+        if self.is_prefix_compressed() {
+            self.page.bytes[HEADER_LEN] = prefix.len() as u8;
+            self.page.set_bytes(HEADER_LEN + 1, prefix.clone());
+        }
+        self.set_number_of_pointer(number_of_pointer as u16);
+        self.set_fragmented_bytes(0);
+
+        let mut end_of_free_space = PAGE_SIZE;
+        for (index, (key, value)) in slots.into_iter().enumerate() {
+            let key_suffix = key[prefix.len()..].to_vec();
+            let pointer_offset = self.pointer_offset(index);
+            let (key_size, value_size) = (key_suffix.len() as u16, value.len() as u16);
+            end_of_free_space -= self.new_pointer(0, key_size, value_size).slot_size() as usize;
+            let pointer = self.new_pointer(end_of_free_space as u16, key_size, value_size);
+            self.page.set_bytes(pointer_offset, pointer.to_bytes());
+            self.page.set_bytes(end_of_free_space, pointer.slot_header());
+            self.page.set_bytes(pointer.key_range().start, key_suffix);
+            self.page.set_bytes(pointer.value_range().start, value);
+        }
+        self.set_end_of_free_space(end_of_free_space as u16);
+    }
 }
 
 impl<K: Debug, V: SlotBytes + Debug, P: Pointer + Debug> Slotted<K, V, P>
-    where K: Ord + SlotBytes 
+    where K: SlotBytes 
 {
     fn search_pointer(&self, key: &K) -> Option<usize> {
-        self.keys().binary_search(key).ok()
+        self.keys().binary_search_by(|k| (self.compare)(k, key)).ok()
     }
 
     fn pointer_index_to_pointer(&self, key_index: usize) -> P {
-        let pointer = Self::pointer_offset(key_index.clone());
-        Self::offset_to_pointer(&self.page.bytes, pointer)
+        let pointer = self.pointer_offset(key_index);
+        P::read(&self.page.bytes, pointer, self.pointer_format())
     }
 
-    fn offset_to_pointer(chunk: &[u8], offset: usize) -> P {
-        let bytes = &chunk[range(offset, P::len())];
-        P::from_bytes(bytes)
+    fn new_pointer(&self, offset: u16, key_size: u16, value_size: u16) -> P {
+        P::new(offset, key_size, value_size, self.pointer_format())
     }
 
-    fn pointers_range(&self) -> Range<usize> {
-        HEADER_LEN..self.start_of_free_space()
+    fn key_bytes(&self, pointer: &P) -> Vec<u8> {
+        let mut bytes = self.prefix();
+        bytes.extend_from_slice(&self.page.bytes[pointer.key_range()]);
+        bytes
     }
 
     fn start_of_free_space(&self) -> usize {
         let number_of_pointer = self.number_of_pointer();
-        Self::pointer_offset(number_of_pointer as usize)
+        self.pointer_offset(number_of_pointer as usize)
+    }
+
+    fn header_len(&self) -> usize {
+        if self.is_prefix_compressed() {
+            HEADER_LEN + 1 + self.page.bytes[HEADER_LEN] as usize
+        } else {
+            HEADER_LEN
+        }
     }
 
     fn increment_number_of_pointer(&mut self) {
@@ -268,8 +488,8 @@ impl<K: Debug, V: SlotBytes + Debug, P: Pointer + Debug> Slotted<K, V, P>
     }
 
     fn set_number_of_pointer(&mut self, number: u16) {
-        let number = number & 0x7FFF;
-        let current = self.page.u16_bytes(0) & 0x8000;
+        let number = number & NUMBER_OF_POINTER_MASK;
+        let current = self.page.u16_bytes(0) & !NUMBER_OF_POINTER_MASK;
         self.page.set_u16_bytes(0, number | current);
     }
 
@@ -278,22 +498,34 @@ impl<K: Debug, V: SlotBytes + Debug, P: Pointer + Debug> Slotted<K, V, P>
     }
 
     fn number_of_pointer(&self) -> u16 {
-        self.page.u16_bytes(0) & 0x7FFF
+        self.page.u16_bytes(0) & NUMBER_OF_POINTER_MASK
+    }
+
+    fn set_fragmented_bytes(&mut self, number: u16) {
+        self.page.set_u16_bytes(6, number);
     }
 
     fn end_of_free_space(&self) -> u16 {
         self.page.u16_bytes(2)
     }
 
-    fn pointer_offset(index: usize) -> usize {
-        HEADER_LEN + Self::pointer_size() * index
+    fn pointer_offset(&self, index: usize) -> usize {
+        self.header_len() + self.pointer_size() * index
     }
 
-    fn pointer_size() -> usize {
-        P::len()
+    pub fn pointer_size(&self) -> usize {
+        P::len(self.pointer_format())
     }    
 }
 
 fn range(start: usize, len: usize) -> Range<usize> {
     start..start + len
 }
+
+fn common_prefix(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b.iter())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| *a)
+        .take(u8::MAX as usize)
+        .collect::<Vec<_>>()
+}
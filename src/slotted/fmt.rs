@@ -8,7 +8,7 @@ use crate::slot::SlotBytes;
 
 
 impl<K, V, P> Debug for Slotted<K, V, P>
-    where K: Ord + SlotBytes + Debug,
+    where K: SlotBytes + Debug,
           V: SlotBytes + Debug,
           P: Pointer + Debug,
 {
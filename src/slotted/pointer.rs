@@ -2,30 +2,43 @@ use std::convert::TryInto;
 use std::fmt::Debug;
 use std::fmt::Error;
 use std::fmt::Formatter;
-use std::mem::size_of;
 use std::ops::Range;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PointerFormat {
+    // offset and sizes in the pointer array
+    #[default]
+    Fixed,
+    // offset only in the pointer array, sizes as varints at the head of the slot
+    Varint,
+}
+
 pub trait Pointer {
-    fn new(offset: u16, key_size: u16, value_size: u16) -> Self;
-    fn len() -> usize;
-    fn from_bytes(bytes: &[u8]) -> Self;
+    fn new(offset: u16, key_size: u16, value_size: u16, format: PointerFormat) -> Self;
+    fn len(format: PointerFormat) -> usize;
+    fn read(bytes: &[u8], offset: usize, format: PointerFormat) -> Self;
     fn slot_offset(&self) -> u16;
     fn key_size(&self) -> u16;
     fn value_size(&self) -> u16;
+    fn slot_header(&self) -> Vec<u8>;
     fn key_range(&self) -> Range<usize> {
-        let start = self.slot_offset() as usize;
-        let end = (self.slot_offset() + self.key_size()) as usize;
+        let start = (self.slot_offset() + self.header_size()) as usize;
+        let end = start + self.key_size() as usize;
         start..end
     }
 
     fn value_range(&self) -> Range<usize> {
-        let start = (self.slot_offset() + self.key_size()) as usize;
-        let end = (self.slot_offset() + self.key_size() + self.value_size()) as usize;
+        let start = self.key_range().end;
+        let end = start + self.value_size() as usize;
         start..end
     }
 
+    fn header_size(&self) -> u16 {
+        self.slot_header().len() as u16
+    }
+
     fn slot_size(&self) -> u16 {
-        self.key_size() + self.value_size()
+        self.header_size() + self.key_size() + self.value_size()
     }
     fn to_bytes(&self) -> Vec<u8>;
 }
@@ -34,32 +47,57 @@ pub struct LeafPointer {
     slot_offset: u16,
     key_size: u16,
     value_size: u16,
+    format: PointerFormat,
 }
 
 impl Pointer for LeafPointer {
-    fn new(offset: u16, key_size: u16, value_size: u16) -> Self {
-        LeafPointer { slot_offset: offset, key_size: key_size, value_size: value_size }
+    fn new(offset: u16, key_size: u16, value_size: u16, format: PointerFormat) -> Self {
+        LeafPointer { slot_offset: offset, key_size, value_size, format }
     }
 
-    fn len() -> usize { size_of::<LeafPointer>() }
+    fn len(format: PointerFormat) -> usize {
+        match format {
+            PointerFormat::Fixed => 6,
+            PointerFormat::Varint => 2,
+        }
+    }
 
-    fn from_bytes(bytes: &[u8]) -> Self {
-        let bytes_offset = &bytes[0..2];
-        let offset = u16::from_le_bytes(bytes_offset.try_into().unwrap());
-        let bytes_key_size = &bytes[2..4];
-        let key_size = u16::from_le_bytes(bytes_key_size.try_into().unwrap());
-        let bytes_value_size = &bytes[4..6];
-        let value_size = u16::from_le_bytes(bytes_value_size.try_into().unwrap());
-        LeafPointer { slot_offset: offset, key_size: key_size, value_size: value_size }
+    fn read(bytes: &[u8], offset: usize, format: PointerFormat) -> Self {
+        let slot_offset = u16_at(bytes, offset);
+        match format {
+            PointerFormat::Fixed => {
+                let key_size = u16_at(bytes, offset + 2);
+                let value_size = u16_at(bytes, offset + 4);
+                LeafPointer { slot_offset, key_size, value_size, format }
+            },
+            PointerFormat::Varint => {
+                let (key_size, len) = read_varint(&bytes[slot_offset as usize..]);
+                let (value_size, _) = read_varint(&bytes[slot_offset as usize + len..]);
+                LeafPointer { slot_offset, key_size, value_size, format }
+            },
+        }
     }
     fn slot_offset(&self) -> u16 { self.slot_offset }
-    fn key_size(&self) -> u16 { self.key_size } 
+    fn key_size(&self) -> u16 { self.key_size }
     fn value_size(&self) -> u16 { self.value_size }
 
+    fn slot_header(&self) -> Vec<u8> {
+        match self.format {
+            PointerFormat::Fixed => vec![],
+            PointerFormat::Varint => {
+                let mut bytes = write_varint(self.key_size);
+                bytes.append(&mut write_varint(self.value_size));
+                bytes
+            },
+        }
+    }
+
     fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = self.slot_offset.to_le_bytes().to_vec();
-        bytes.append(&mut self.key_size().to_le_bytes().to_vec());
-        bytes.append(&mut self.value_size().to_le_bytes().to_vec());
+        if self.format == PointerFormat::Fixed {
+            bytes.append(&mut self.key_size().to_le_bytes().to_vec());
+            bytes.append(&mut self.value_size().to_le_bytes().to_vec());
+        }
         bytes
     }
 }
@@ -77,29 +115,45 @@ impl Debug for LeafPointer {
 pub struct BranchPointer {
     slot_offset: u16,
     key_size: u16,
+    format: PointerFormat,
 }
 
 impl Pointer for BranchPointer {
-    fn new(offset: u16, key_size: u16, _value_size: u16) -> Self {
-        BranchPointer { slot_offset: offset, key_size: key_size }
+    fn new(offset: u16, key_size: u16, _value_size: u16, format: PointerFormat) -> Self {
+        BranchPointer { slot_offset: offset, key_size, format }
     }
 
-    fn len() -> usize { size_of::<BranchPointer>() }
+    fn len(format: PointerFormat) -> usize {
+        match format {
+            PointerFormat::Fixed => 4,
+            PointerFormat::Varint => 2,
+        }
+    }
 
-    fn from_bytes(bytes: &[u8]) -> Self {
-        let bytes_offset = &bytes[0..2];
-        let offset = u16::from_le_bytes(bytes_offset.try_into().unwrap());
-        let bytes_key_size = &bytes[2..4];
-        let key_size = u16::from_le_bytes(bytes_key_size.try_into().unwrap());
-        BranchPointer { slot_offset: offset, key_size: key_size }
+    fn read(bytes: &[u8], offset: usize, format: PointerFormat) -> Self {
+        let slot_offset = u16_at(bytes, offset);
+        let key_size = match format {
+            PointerFormat::Fixed => u16_at(bytes, offset + 2),
+            PointerFormat::Varint => read_varint(&bytes[slot_offset as usize..]).0,
+        };
+        BranchPointer { slot_offset, key_size, format }
     }
     fn slot_offset(&self) -> u16 { self.slot_offset }
     fn key_size(&self) -> u16 { self.key_size }
     fn value_size(&self) -> u16 { 2 }
 
+    fn slot_header(&self) -> Vec<u8> {
+        match self.format {
+            PointerFormat::Fixed => vec![],
+            PointerFormat::Varint => write_varint(self.key_size),
+        }
+    }
+
     fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = self.slot_offset.to_le_bytes().to_vec();
-        bytes.append(&mut self.key_size().to_le_bytes().to_vec());
+        if self.format == PointerFormat::Fixed {
+            bytes.append(&mut self.key_size().to_le_bytes().to_vec());
+        }
         bytes
     }
 }
@@ -112,3 +166,29 @@ impl Debug for BranchPointer {
             .finish()
     }
 }
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+// LEB128: 7 bits per byte, high bit set on all but the last byte; a u16 never needs more than 3
+fn write_varint(mut value: u16) -> Vec<u8> {
+    let mut bytes = vec![];
+    while value >= 0x80 {
+        bytes.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+    bytes
+}
+
+fn read_varint(bytes: &[u8]) -> (u16, usize) {
+    let mut value = 0u16;
+    for (i, byte) in bytes.iter().enumerate().take(3) {
+        value |= ((byte & 0x7F) as u16) << (7 * i);
+        if byte & 0x80 == 0 {
+            return (value, i + 1);
+        }
+    }
+    (value, bytes.len().min(3))
+}
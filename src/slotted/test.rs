@@ -1,17 +1,22 @@
 use crate::error::Error;
+use crate::slotted::PageDamage;
 use crate::slotted::Slotted;
 use crate::page::Page;
 use crate::page::PAGE_SIZE;
 use crate::slot::Slot;
 use crate::slotted::pointer::LeafPointer;
+use crate::slotted::pointer::Pointer;
+use crate::slotted::pointer::PointerFormat;
 
 
 type TestSlotted = Slotted::<u16, String, LeafPointer>;
+type PrefixSlotted = Slotted::<String, u8, LeafPointer>;
+type VarintSlotted = Slotted::<u16, u16, LeafPointer>;
 
 #[test]
 fn test_insert_one() {
     let page = Page::new(Default::default());
-    let mut slotted = TestSlotted::create(page);
+    let mut slotted = TestSlotted::create(page, u16::cmp);
     let _ = slotted.insert(&Slot::new(2u16, "abc".to_string()));
     let _ = slotted.insert(&Slot::new(7u16, "ありがと".to_string()));
     let _ = slotted.insert(&Slot::new(5u16, "defg".to_string()));
@@ -23,7 +28,7 @@ fn test_insert_one() {
 #[test]
 fn test_pointers_sorted() {
     let page = Page::new(Default::default());
-    let mut slotted = TestSlotted::create(page);
+    let mut slotted = TestSlotted::create(page, u16::cmp);
     let _ = slotted.insert(&Slot::new(2u16, "abc".to_string()));
     let _ = slotted.insert(&Slot::new(7u16, "ありがと".to_string()));
     let _ = slotted.insert(&Slot::new(5u16, "defg".to_string()));
@@ -36,7 +41,7 @@ fn test_pointers_sorted() {
 #[test]
 fn test_pointers_full() {
     let page = Page::new(Default::default());
-    let mut slotted = TestSlotted::create(page);
+    let mut slotted = TestSlotted::create(page, u16::cmp);
     let _ = slotted.insert(&Slot::new(2u16, "abc".to_string()));
     let _ = slotted.insert(&Slot::new(7u16, "ありがと".to_string()));
     let _ = slotted.insert(&Slot::new(5u16, "defg".to_string()));
@@ -51,7 +56,7 @@ fn test_pointers_full() {
 #[test]
 fn test_search_hit() {
     let page = Page::new(Default::default());
-    let mut slotted = TestSlotted::create(page);
+    let mut slotted = TestSlotted::create(page, u16::cmp);
     let _ = slotted.insert(&Slot::new(2u16, "abc".to_string()));
     assert_eq!(slotted.search(&2u16), Some("abc".to_string()));
 }
@@ -59,7 +64,7 @@ fn test_search_hit() {
 #[test]
 fn test_search_notfound() {
     let page = Page::new(Default::default());
-    let mut slotted = TestSlotted::create(page);
+    let mut slotted = TestSlotted::create(page, u16::cmp);
     let _ = slotted.insert(&Slot::new(2u16, "abc".to_string()));
     assert_eq!(slotted.search(&5u16), None);
 }
@@ -67,7 +72,7 @@ fn test_search_notfound() {
 #[test]
 fn test_delete_notfound() {
     let page = Page::new(Default::default());
-    let mut slotted = TestSlotted::create(page);
+    let mut slotted = TestSlotted::create(page, u16::cmp);
     let _ = slotted.insert(&Slot::new(2u16, "abc".to_string()));
     assert!(slotted.delete(&5).is_err());
 }
@@ -75,7 +80,7 @@ fn test_delete_notfound() {
 #[test]
 fn test_delete_one() {
     let page = Page::new(Default::default());
-    let mut slotted = TestSlotted::create(page);
+    let mut slotted = TestSlotted::create(page, u16::cmp);
     let _ = slotted.insert(&Slot::new(2u16, "abc".to_string()));
     assert!(slotted.delete(&2).is_ok());
     let mut res = [0u8; PAGE_SIZE];
@@ -85,7 +90,7 @@ fn test_delete_one() {
 
 #[test]
 fn test_delete_multi() {
-    let mut slotted1 = TestSlotted::create(Page::new(Default::default()));
+    let mut slotted1 = TestSlotted::create(Page::new(Default::default()), u16::cmp);
     let target_value = "defg";
     let _ = slotted1.insert(&Slot::new(2u16, "abc".to_string()));
     let _ = slotted1.insert(&Slot::new(7u16, "ありがと".to_string()));
@@ -93,7 +98,7 @@ fn test_delete_multi() {
     assert!(slotted1.delete(&5).is_ok());
 
 
-    let mut slotted2 = TestSlotted::create(Page::new(Default::default()));
+    let mut slotted2 = TestSlotted::create(Page::new(Default::default()), u16::cmp);
     let _ = slotted2.insert(&Slot::new(2u16, "abc".to_string()));
     let _ = slotted2.insert(&Slot::new(7u16, "ありがと".to_string()));
     assert_eq!(slotted1.page.bytes, slotted2.page.bytes);
@@ -101,14 +106,162 @@ fn test_delete_multi() {
 
 #[test]
 fn test_delete_transfer() {
-    let mut slotted1 = TestSlotted::create(Page::new(Default::default()));
+    let mut slotted1 = TestSlotted::create(Page::new(Default::default()), u16::cmp);
     let _ = slotted1.insert(&Slot::new(13u16, "abc".to_string()));
     let _ = slotted1.insert(&Slot::new(7u16, "ぽぽ".to_string()));
     assert!(slotted1.delete(&13).is_ok());
+    slotted1.compact();
 
-    let mut slotted2 = TestSlotted::create(Page::new(Default::default()));
+    let mut slotted2 = TestSlotted::create(Page::new(Default::default()), u16::cmp);
     let _ = slotted2.insert(&Slot::new(7u16, "ぽぽ".to_string()));
     println!("{:?}", slotted2);
 
     assert_eq!(slotted1.page.bytes, slotted2.page.bytes);
 }
+
+#[test]
+fn test_delete_tombstone() {
+    let mut slotted = TestSlotted::create(Page::new(Default::default()), u16::cmp);
+    let _ = slotted.insert(&Slot::new(13u16, "abc".to_string()));
+    let _ = slotted.insert(&Slot::new(7u16, "ぽぽ".to_string()));
+    assert!(slotted.delete(&13).is_ok());
+    assert_eq!(slotted.fragmented_bytes(), 5);
+    assert_eq!(slotted.search(&7), Some("ぽぽ".to_string()));
+
+    slotted.compact();
+    assert_eq!(slotted.fragmented_bytes(), 0);
+    assert_eq!(slotted.search(&7), Some("ぽぽ".to_string()));
+}
+
+#[test]
+fn test_update_same_size() {
+    let mut slotted = TestSlotted::create(Page::new(Default::default()), u16::cmp);
+    let _ = slotted.insert(&Slot::new(2u16, "abc".to_string()));
+    let _ = slotted.insert(&Slot::new(5u16, "defg".to_string()));
+    assert!(slotted.update(&2, &"xyz".to_string()).is_ok());
+    assert_eq!(slotted.search(&2), Some("xyz".to_string()));
+    assert_eq!(slotted.fragmented_bytes(), 0);
+}
+
+#[test]
+fn test_update_free_space() {
+    let mut slotted = TestSlotted::create(Page::new(Default::default()), u16::cmp);
+    let _ = slotted.insert(&Slot::new(2u16, "abc".to_string()));
+    let _ = slotted.insert(&Slot::new(5u16, "defg".to_string()));
+    assert!(slotted.update(&2, &"abcdef".to_string()).is_ok());
+    assert_eq!(slotted.slots(), [(2, "abcdef".to_string()), (5, "defg".to_string())]);
+    assert_eq!(slotted.fragmented_bytes(), 5);
+}
+
+#[test]
+fn test_update_full() {
+    let mut slotted = TestSlotted::create(Page::new(Default::default()), u16::cmp);
+    let _ = slotted.insert(&Slot::new(2u16, "abc".to_string()));
+    let _ = slotted.insert(&Slot::new(7u16, "ありがと".to_string()));
+    let _ = slotted.insert(&Slot::new(5u16, "defg".to_string()));
+    assert_eq!(slotted.update(&2, &"abcdefghijkl".to_string()), Err(Error::FullLeaf));
+    assert_eq!(slotted.update(&3, &"abc".to_string()), Err(Error::NotFound));
+    assert_eq!(slotted.search(&2), Some("abc".to_string()));
+}
+
+#[test]
+fn test_prefix_insert() {
+    let mut slotted = PrefixSlotted::create(Page::new(Default::default()), String::cmp);
+    slotted.set_prefix_compression(true);
+    let _ = slotted.insert(&Slot::new("apple".to_string(), 1));
+    let _ = slotted.insert(&Slot::new("applet".to_string(), 2));
+    slotted.compact();
+    assert_eq!(slotted.prefix(), b"apple");
+    let _ = slotted.insert(&Slot::new("application".to_string(), 3));
+    assert_eq!(slotted.prefix(), b"appl");
+    assert_eq!(slotted.keys(), ["apple", "applet", "application"]);
+    assert_eq!(slotted.search(&"applet".to_string()), Some(2));
+    assert_eq!(slotted.search(&"application".to_string()), Some(3));
+}
+
+#[test]
+fn test_prefix_capacity() {
+    let keys = ["prefix_a", "prefix_b", "prefix_c", "prefix_d", "prefix_e"];
+    let mut plain = PrefixSlotted::create(Page::new(Default::default()), String::cmp);
+    let mut prefixed = PrefixSlotted::create(Page::new(Default::default()), String::cmp);
+    prefixed.set_prefix_compression(true);
+    let _ = prefixed.insert(&Slot::new(keys[0].to_string(), 0));
+    let _ = prefixed.insert(&Slot::new(keys[1].to_string(), 1));
+    prefixed.compact();
+
+    let plain_count = keys.iter().enumerate()
+        .filter(|(i, k)| plain.insert(&Slot::new(k.to_string(), *i as u8)).is_ok())
+        .count();
+    let prefixed_count = 2 + keys[2..].iter().enumerate()
+        .filter(|(i, k)| prefixed.insert(&Slot::new(k.to_string(), *i as u8 + 2)).is_ok())
+        .count();
+    assert_eq!(plain_count, 3);
+    assert_eq!(prefixed_count, 5);
+    assert_eq!(prefixed.keys(), keys);
+}
+
+#[test]
+fn test_prefix_delete_compact() {
+    let mut slotted = PrefixSlotted::create(Page::new(Default::default()), String::cmp);
+    slotted.set_prefix_compression(true);
+    let _ = slotted.insert(&Slot::new("banana".to_string(), 1));
+    let _ = slotted.insert(&Slot::new("bandana".to_string(), 2));
+    let _ = slotted.insert(&Slot::new("cherry".to_string(), 3));
+    assert!(slotted.delete(&"cherry".to_string()).is_ok());
+    slotted.compact();
+    assert_eq!(slotted.prefix(), b"ban");
+    assert_eq!(slotted.slots(), [("banana".to_string(), 1), ("bandana".to_string(), 2)]);
+}
+
+#[test]
+fn test_varint_capacity() {
+    let mut fixed = VarintSlotted::create(Page::new(Default::default()), u16::cmp);
+    let mut varint = VarintSlotted::create(Page::new(Default::default()), u16::cmp);
+    varint.set_pointer_format(PointerFormat::Varint);
+    let fixed_count = (0..10u16)
+        .filter(|i| fixed.insert(&Slot::new(*i, *i * 10)).is_ok())
+        .count();
+    let varint_count = (0..10u16)
+        .filter(|i| varint.insert(&Slot::new(*i, *i * 10)).is_ok())
+        .count();
+    assert_eq!(fixed_count, 5);
+    assert_eq!(varint_count, 7);
+    assert_eq!(varint.search(&6), Some(60));
+    assert_eq!(varint.keys(), [0, 1, 2, 3, 4, 5, 6]);
+}
+
+#[test]
+fn test_varint_delete_update() {
+    let mut slotted = TestSlotted::create(Page::new(Default::default()), u16::cmp);
+    let _ = slotted.insert(&Slot::new(3u16, "c".to_string()));
+    slotted.set_pointer_format(PointerFormat::Varint);
+    assert_eq!(slotted.pointer_format(), PointerFormat::Varint);
+    let _ = slotted.insert(&Slot::new(1u16, "a".to_string()));
+    let _ = slotted.insert(&Slot::new(2u16, "b".to_string()));
+    assert!(slotted.delete(&1).is_ok());
+    assert!(slotted.update(&2, &"bbbb".to_string()).is_ok());
+    slotted.compact();
+    assert_eq!(slotted.fragmented_bytes(), 0);
+    assert_eq!(slotted.slots(), [(2, "bbbb".to_string()), (3, "c".to_string())]);
+}
+
+#[test]
+fn test_damage() {
+    let mut slotted = TestSlotted::create(Page::new(Default::default()), u16::cmp);
+    let _ = slotted.insert(&Slot::new(13u16, "abc".to_string()));
+    let _ = slotted.insert(&Slot::new(7u16, "ぽぽ".to_string()));
+    assert!(slotted.delete(&13).is_ok());
+    assert_eq!(slotted.damage(), None);
+
+    // half a character
+    let value_start = slotted.pointers()[0].value_range().start;
+    slotted.page.bytes[value_start] = 0xFF;
+    assert_eq!(slotted.damage(), Some(PageDamage::Undecodable(0)));
+
+    slotted.page.set_u16_bytes(6, 0);
+    assert_eq!(slotted.damage(), Some(PageDamage::FragmentedBytes));
+    slotted.page.set_u16_bytes(2, 12);
+    assert_eq!(slotted.damage(), Some(PageDamage::PointersOverrunSlots));
+    slotted.page.set_u16_bytes(2, PAGE_SIZE as u16 - 2);
+    assert_eq!(slotted.damage(), Some(PageDamage::SlotOutOfPage(0)));
+}
@@ -1,56 +1,476 @@
-use std::marker::PhantomData;
-use std::fs::File;
-use std::fs::OpenOptions;
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+use std::sync::RwLock;
+use std::sync::RwLockReadGuard;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 
+use crate::latch::LatchMode;
+use crate::latch::Latches;
+use crate::meta::Meta;
+use crate::options::Durability;
 use crate::page::PAGE_SIZE;
 use crate::page::Page;
+use crate::store::PageStore;
 
+const CACHE_PAGES: usize = 256;
+// page ids listed by one directory page of the journal
+const DIRECTORY_LEN: usize = PAGE_SIZE / 2;
 
-pub struct Storage<K, V> {
-    pub next_page_id: u16, 
-    file: File,
-    _phantom_key: PhantomData<fn() -> K>,
-    _phantom_value: PhantomData<fn() -> V>,
+
+// page images overwritten while a snapshot is pinned, tagged with the newest pinned version
+type Images = HashMap<u16, Vec<(u64, [u8; PAGE_SIZE])>>;
+
+// shared by every tree in the file; page 0 is always a meta page and keeps the head of the free list
+pub struct Storage {
+    store: Box<dyn PageStore>,
+    pub latches: Latches,
+    free_list_head: Mutex<u16>,
+    // held shared by every tree write and exclusively by flush, so a flush never commits half a split
+    writes: RwLock<()>,
+    // write-through, io on a miss happens under the lock so a stale read never lands in the cache
+    cache: Mutex<HashMap<u16, [u8; PAGE_SIZE]>>,
+    // one counter for every tree in the file, images of any tree are tagged with it
+    version: AtomicU64,
+    pinned: Mutex<BTreeMap<u64, usize>>,
+    pinned_count: AtomicUsize,
+    images: Mutex<Images>,
+    durability: Durability,
+    // pages written since the last flush, OnFlush keeps them off the store until it commits them
+    dirty: Mutex<HashMap<u16, [u8; PAGE_SIZE]>>,
+    // OnFlush cuts the file at the next flush, until then the ids from here on are allocated again
+    truncated_len: Mutex<Option<u16>>,
+    // the first write that failed, returned by the next flush as not every caller can return it
+    failed: Mutex<Option<io::Error>>,
+    // open tree handles by meta page, a Db does not drop a tree that still has one
+    handles: Mutex<HashMap<u16, usize>>,
 }
 
-impl<K, V> Storage<K, V> {
-    pub fn from_path(file_path: impl AsRef<Path>) -> Self {
-        let file = Self::open_file(file_path);
-        let file_size = file.metadata().unwrap().len();
-        let next_page_id = file_size / PAGE_SIZE as u64;
-        Storage::new(next_page_id as u16, file)
+impl Storage {
+    // a file left in the middle of a flush is replayed first, it does not open when that fails
+    pub fn new(store: Box<dyn PageStore>, durability: Durability) -> io::Result<Self> {
+        let storage = Storage {
+            store,
+            latches: Latches::new(),
+            free_list_head: Mutex::new(0),
+            writes: RwLock::new(()),
+            cache: Mutex::new(HashMap::new()),
+            version: AtomicU64::new(0),
+            pinned: Mutex::new(BTreeMap::new()),
+            pinned_count: AtomicUsize::new(0),
+            images: Mutex::new(HashMap::new()),
+            durability,
+            dirty: Mutex::new(HashMap::new()),
+            truncated_len: Mutex::new(None),
+            failed: Mutex::new(None),
+            handles: Mutex::new(HashMap::new()),
+        };
+        if !storage.store.is_empty() {
+            storage.recover()?;
+            let mut page = Page::new(0);
+            storage.store.read_page(0, &mut page.bytes)?;
+            *storage.free_list_head.lock().unwrap() = Meta::new(page).free_list_head();
+        }
+        Ok(storage)
     }
     
-    pub fn allocate_page(&mut self) -> Page {
-        let id = self.next_page_id;
-        self.next_page_id += 1;
-        Page::new(id)
+    pub fn next_page_id(&self) -> u16 {
+        match *self.truncated_len.lock().unwrap() {
+            Some(len) => len.min(self.store.len()),
+            None => self.store.len(),
+        }
+    }
+
+    pub fn open_handle(&self, meta_page_id: u16) {
+        *self.handles.lock().unwrap().entry(meta_page_id).or_default() += 1;
+    }
+
+    pub fn close_handle(&self, meta_page_id: u16) {
+        let mut handles = self.handles.lock().unwrap();
+        if let Some(count) = handles.get_mut(&meta_page_id) {
+            *count -= 1;
+            if *count == 0 {
+                handles.remove(&meta_page_id);
+            }
+        }
+    }
+
+    pub fn has_handles(&self, meta_page_id: u16) -> bool {
+        self.handles.lock().unwrap().contains_key(&meta_page_id)
+    }
+
+    pub fn allocate_page(&self) -> io::Result<Page> {
+        let mut free_list_head = self.free_list_head.lock().unwrap();
+        if *free_list_head == 0 {
+            return Ok(Page::new(self.allocate_id()));
+        }
+        let mut page = Page::new(*free_list_head);
+        self.read_page(&mut page)?;
+        *free_list_head = page.u16_bytes(0);
+        self.write_free_list_head(*free_list_head)?;
+        Ok(Page::new(page.id))
+    }
+
+    // a freed page keeps the id of the next free page in its first bytes
+    pub fn free_page(&self, page_id: u16) -> io::Result<()> {
+        let mut free_list_head = self.free_list_head.lock().unwrap();
+        let mut page = Page::new(page_id);
+        page.set_u16_bytes(0, *free_list_head);
+        self.write_page(&mut page)?;
+        *free_list_head = page_id;
+        self.write_free_list_head(page_id)
+    }
+
+    pub fn free_page_ids(&self) -> io::Result<Vec<u16>> {
+        let free_list_head = self.free_list_head.lock().unwrap();
+        self.free_list(*free_list_head)
+    }
+
+    // build allocates the free pages and the freed ones lowest first, then new ones past the end;
+    // the pages it leaves become the free list, those at the end of the file are cut off
+    pub fn reuse_pages(&self, freed: &[u16], build: impl FnOnce(&mut dyn FnMut() -> u16) -> io::Result<()>) -> io::Result<u16> {
+        let mut free_list_head = self.free_list_head.lock().unwrap();
+        let mut page_ids = self.free_list(*free_list_head)?;
+        page_ids.extend_from_slice(freed);
+        page_ids.sort_unstable();
+        page_ids.dedup();
+        let mut unused = page_ids.into_iter();
+        build(&mut || unused.next().unwrap_or_else(|| self.allocate_id()))?;
+
+        let mut free = unused.collect::<Vec<_>>();
+        let len = self.next_page_id();
+        let mut new_len = len;
+        while free.last() == Some(&(new_len - 1)) {
+            free.pop();
+            new_len -= 1;
+        }
+        for (index, page_id) in free.iter().enumerate() {
+            let mut page = Page::new(*page_id);
+            page.set_u16_bytes(0, free.get(index + 1).cloned().unwrap_or(0));
+            self.write_page(&mut page)?;
+        }
+        *free_list_head = free.first().cloned().unwrap_or(0);
+        self.write_free_list_head(*free_list_head)?;
+        self.truncate(new_len)?;
+        Ok(len - new_len)
+    }
+
+    // held by a tree for the whole of a write, which is what a snapshot taken after it sees
+    pub fn begin_write(&self) -> RwLockReadGuard<'_, ()> {
+        self.bump_version();
+        self.writes.read().unwrap()
+    }
+
+    pub fn write_page(&self, page: &mut Page) -> io::Result<()> {
+        let mut cache = self.cache.lock().unwrap();
+        if self.pinned_count.load(Ordering::SeqCst) > 0 {
+            let mut images = self.images.lock().unwrap();
+            self.preserve(&mut images, page.id)?;
+        }
+        Self::cache_page(&mut cache, page);
+        if self.durability == Durability::OnFlush {
+            self.dirty.lock().unwrap().insert(page.id, page.bytes);
+            return Ok(());
+        }
+        self.dirty.lock().unwrap().remove(&page.id);
+        let mut result = self.store.write_page(page.id, &page.bytes);
+        if result.is_ok() && self.durability == Durability::EveryWrite {
+            result = self.store.sync();
+        }
+        self.keep_error(result)
+    }
+
+    // OnFlush commits the pages written since the last flush all at once, the others only sync
+    pub fn flush(&self) -> io::Result<()> {
+        let _writes = self.writes.write().unwrap();
+        let _free_list_head = self.free_list_head.lock().unwrap();
+        let _cache = self.cache.lock().unwrap();
+        if let Some(error) = self.failed.lock().unwrap().take() {
+            return Err(error);
+        }
+        match self.durability {
+            Durability::OnFlush => self.commit(),
+            Durability::EveryWrite => self.store.sync(),
+            Durability::Never => Ok(()),
+        }
+    }
+
+    pub fn read_page(&self, page: &mut Page) -> io::Result<()> {
+        let mut cache = self.cache.lock().unwrap();
+        self.read_cached(&mut cache, page)
+    }
+
+    // the page as it was when version was pinned; the cache is locked first, as by writes
+    pub fn read_page_at(&self, page: &mut Page, version: u64) -> io::Result<()> {
+        let mut cache = self.cache.lock().unwrap();
+        let images = self.images.lock().unwrap();
+        let image = images.get(&page.id)
+            .and_then(|images| images.iter().find(|(tag, _)| *tag >= version));
+        match image {
+            Some((_, bytes)) => {
+                page.bytes = *bytes;
+                Ok(())
+            }
+            None => self.read_cached(&mut cache, page),
+        }
+    }
+
+    // bumped by every write and by every snapshot, so a snapshot never shares its version with a write in flight
+    pub fn bump_version(&self) -> u64 {
+        self.version.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    pub fn pin(&self, version: u64) {
+        let mut pinned = self.pinned.lock().unwrap();
+        *pinned.entry(version).or_default() += 1;
+        self.pinned_count.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn unpin(&self, version: u64) {
+        let mut images = self.images.lock().unwrap();
+        let mut pinned = self.pinned.lock().unwrap();
+        if let Some(count) = pinned.get_mut(&version) {
+            *count -= 1;
+            if *count == 0 {
+                pinned.remove(&version);
+            }
+        }
+        self.pinned_count.fetch_sub(1, Ordering::SeqCst);
+
+        // keep only the images some pinned version still reads
+        for tagged in images.values_mut() {
+            let needed = pinned.keys()
+                .filter_map(|version| tagged.iter().find(|(tag, _)| tag >= version).map(|(tag, _)| *tag))
+                .collect::<Vec<_>>();
+            tagged.retain(|(tag, _)| needed.contains(tag));
+        }
+        images.retain(|_, tagged| !tagged.is_empty());
     }
 
-    pub fn write_page(&mut self, page: &mut Page) {
-        let _ = page.write(&mut self.file);
+    #[cfg(test)]
+    pub fn image_count(&self) -> usize {
+        self.images.lock().unwrap().values().map(|tagged| tagged.len()).sum()
+    }
+
+    fn preserve(&self, images: &mut Images, page_id: u16) -> io::Result<()> {
+        let newest = match self.pinned.lock().unwrap().keys().next_back() {
+            Some(version) => *version,
+            None => return Ok(()),
+        };
+        let tagged = images.entry(page_id).or_default();
+        if tagged.last().is_some_and(|(tag, _)| *tag == newest) {
+            return Ok(());
+        }
+        if let Some(bytes) = self.dirty.lock().unwrap().get(&page_id) {
+            tagged.push((newest, *bytes));
+            return Ok(());
+        }
+        // pages allocated after the snapshot are not written yet and need no image
+        let mut bytes = [0; PAGE_SIZE];
+        match self.store.read_page(page_id, &mut bytes) {
+            Ok(()) => tagged.push((newest, bytes)),
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {}
+            Err(error) => return Err(error),
+        }
+        Ok(())
     }
 
-    pub fn read_page(&mut self, page: &mut Page) {
-        let _ = page.read(&mut self.file);
+    // snapshots keep reading the pages cut off; OnFlush leaves the file as it is until the next flush
+    fn truncate(&self, len: u16) -> io::Result<()> {
+        let mut cache = self.cache.lock().unwrap();
+        if self.pinned_count.load(Ordering::SeqCst) > 0 {
+            let mut images = self.images.lock().unwrap();
+            for page_id in len..self.next_page_id() {
+                self.preserve(&mut images, page_id)?;
+            }
+        }
+        cache.retain(|page_id, _| *page_id < len);
+        self.dirty.lock().unwrap().retain(|page_id, _| *page_id < len);
+        if self.durability == Durability::OnFlush {
+            *self.truncated_len.lock().unwrap() = Some(len);
+            return Ok(());
+        }
+        self.store.truncate(len)
     }
 
-    fn new(next_page_id: u16, file: File) -> Self {
-        Storage::<K, V> {
-            next_page_id: next_page_id, 
-            file: file,
-            _phantom_key: PhantomData,
-            _phantom_value: PhantomData,
+    fn allocate_id(&self) -> u16 {
+        let mut truncated_len = self.truncated_len.lock().unwrap();
+        match *truncated_len {
+            Some(len) if len < self.store.len() => {
+                *truncated_len = Some(len + 1);
+                len
+            },
+            _ => {
+                *truncated_len = None;
+                self.store.allocate()
+            },
         }
     }
 
-    fn open_file(file_path: impl AsRef<Path>) -> File { 
-        OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(file_path).unwrap()
+    // the dirty pages go to a journal past the end of the file first; once page 0 points at it
+    // a crash is replayed on open, before that the file is as the last flush left it
+    fn commit(&self) -> io::Result<()> {
+        let mut dirty = self.dirty.lock().unwrap();
+        let committed_len = self.next_page_id();
+        if dirty.is_empty() && committed_len == self.store.len() {
+            return Ok(());
+        }
+        let mut page_ids = dirty.keys().cloned().collect::<Vec<_>>();
+        page_ids.sort_unstable();
+        let start = self.store.len();
+        for chunk in page_ids.chunks(DIRECTORY_LEN) {
+            let mut directory = [0; PAGE_SIZE];
+            for (index, page_id) in chunk.iter().enumerate() {
+                directory[2 * index..2 * index + 2].copy_from_slice(&page_id.to_le_bytes());
+            }
+            self.store.write_page(self.store.allocate(), &directory)?;
+        }
+        for page_id in &page_ids {
+            self.store.write_page(self.store.allocate(), &dirty[page_id])?;
+        }
+        self.store.sync()?;
+
+        let mut meta = Meta::new(Page::new(0));
+        match self.store.read_page(0, &mut meta.page.bytes) {
+            // page 0 of a new file is not on disk before its first flush
+            Err(error) if error.kind() != io::ErrorKind::UnexpectedEof => return Err(error),
+            _ => {},
+        }
+        meta.set_journal(start, page_ids.len() as u16, committed_len);
+        self.store.write_page(0, &meta.page.bytes)?;
+        self.store.sync()?;
+
+        self.apply(&dirty, start, committed_len)?;
+        dirty.clear();
+        *self.truncated_len.lock().unwrap() = None;
+        Ok(())
+    }
+
+    // page 0 goes last; it keeps pointing at the journal until the file is cut to committed_len
+    fn apply(&self, pages: &HashMap<u16, [u8; PAGE_SIZE]>, start: u16, committed_len: u16) -> io::Result<()> {
+        for (page_id, bytes) in pages.iter().filter(|(page_id, _)| **page_id != 0) {
+            self.store.write_page(*page_id, bytes)?;
+        }
+        self.store.sync()?;
+        let mut meta = Meta::new(Page::new(0));
+        match pages.get(&0) {
+            Some(bytes) => meta.page.bytes = *bytes,
+            None => self.store.read_page(0, &mut meta.page.bytes)?,
+        }
+        meta.set_journal(start, 0, committed_len);
+        self.store.write_page(0, &meta.page.bytes)?;
+        self.store.sync()?;
+        self.store.truncate(committed_len)?;
+        meta.set_journal(0, 0, committed_len);
+        self.store.write_page(0, &meta.page.bytes)?;
+        self.store.sync()
+    }
+
+    // replays the journal of a flush cut short after page 0 pointed at it, and cuts off one cut short
+    // before; a read-only store cannot put the pages in place, so it fails until a writer opens the file
+    fn recover(&self) -> io::Result<()> {
+        let mut meta = Meta::new(Page::new(0));
+        match self.store.read_page(0, &mut meta.page.bytes) {
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            result => result?,
+        }
+        let (start, pages, committed_len) = (meta.journal_start(), meta.journal_pages(), meta.committed_len());
+        if committed_len == 0 {
+            return Ok(());
+        }
+        let mut journaled = HashMap::new();
+        let directory_pages = (pages as usize).div_ceil(DIRECTORY_LEN);
+        for index in 0..pages as usize {
+            let mut directory = [0; PAGE_SIZE];
+            self.store.read_page(start + (index / DIRECTORY_LEN) as u16, &mut directory)?;
+            let offset = 2 * (index % DIRECTORY_LEN);
+            let page_id = u16::from_le_bytes([directory[offset], directory[offset + 1]]);
+            let mut bytes = [0; PAGE_SIZE];
+            self.store.read_page(start + (directory_pages + index) as u16, &mut bytes)?;
+            journaled.insert(page_id, bytes);
+        }
+        *self.dirty.lock().unwrap() = journaled;
+        *self.truncated_len.lock().unwrap() = Some(committed_len);
+
+        let mut dirty = self.dirty.lock().unwrap();
+        if start != 0 {
+            self.apply(&dirty, start, committed_len)?;
+        } else if self.store.len() > committed_len {
+            self.store.truncate(committed_len)?;
+        }
+        // only OnFlush keeps the length up to date, other writes may grow the file past it
+        if self.durability != Durability::OnFlush {
+            meta.set_journal(0, 0, 0);
+            if let Some(bytes) = dirty.get(&0) {
+                meta.page.bytes = *bytes;
+            }
+            self.store.write_page(0, &meta.page.bytes)?;
+        }
+        dirty.clear();
+        *self.truncated_len.lock().unwrap() = None;
+        Ok(())
+    }
+
+    // stops after as many pages as the file has, a damaged list may loop
+    fn free_list(&self, free_list_head: u16) -> io::Result<Vec<u16>> {
+        let mut page_ids = vec![];
+        let mut page_id = free_list_head;
+        while page_id != 0 && page_ids.len() < self.next_page_id() as usize {
+            page_ids.push(page_id);
+            let mut page = Page::new(page_id);
+            self.read_page(&mut page)?;
+            page_id = page.u16_bytes(0);
+        }
+        Ok(page_ids)
+    }
+
+    // a page that fails to read is left as it was, not filled with what the store got so far
+    fn read_cached(&self, cache: &mut HashMap<u16, [u8; PAGE_SIZE]>, page: &mut Page) -> io::Result<()> {
+        if let Some(bytes) = self.dirty.lock().unwrap().get(&page.id) {
+            page.bytes = *bytes;
+        } else if let Some(bytes) = cache.get(&page.id) {
+            page.bytes = *bytes;
+        } else {
+            let mut bytes = [0; PAGE_SIZE];
+            self.store.read_page(page.id, &mut bytes)?;
+            page.bytes = bytes;
+            Self::cache_page(cache, page);
+        }
+        Ok(())
+    }
+
+    fn cache_page(cache: &mut HashMap<u16, [u8; PAGE_SIZE]>, page: &Page) {
+        if cache.len() >= CACHE_PAGES && !cache.contains_key(&page.id) {
+            let evicted = *cache.keys().next().unwrap();
+            cache.remove(&evicted);
+        }
+        cache.insert(page.id, page.bytes);
+    }
+
+    pub fn keep_error(&self, result: io::Result<()>) -> io::Result<()> {
+        if let Err(error) = &result {
+            self.failed.lock().unwrap().get_or_insert_with(|| io::Error::new(error.kind(), error.to_string()));
+        }
+        result
+    }
+
+    fn write_free_list_head(&self, page_id: u16) -> io::Result<()> {
+        let _meta_latch = self.latches.lock(0, LatchMode::Exclusive);
+        let mut page = Page::new(0);
+        self.read_page(&mut page)?;
+        let mut meta = Meta::new(page);
+        meta.set_free_list_head(page_id);
+        self.write_page(&mut meta.page)
+    }
+}
+
+// dirty pages would be lost otherwise
+impl Drop for Storage {
+    fn drop(&mut self) {
+        let _ = self.flush();
     }
 }
 
@@ -62,13 +482,15 @@ mod test {
 
     use crate::storage::Storage;
     use crate::storage::PAGE_SIZE;
+    use crate::options::Durability;
+    use crate::store::FileStore;
 
     #[test]
     fn test_from_path_zero() {
         let temp_file_path = "test_from_path_zero";
-        let storage = Storage::<u16, &str>::from_path(temp_file_path);
+        let storage = Storage::new(Box::new(FileStore::open(temp_file_path).unwrap()), Durability::default()).unwrap();
         let _ = remove_file(temp_file_path);
-        assert_eq!(storage.next_page_id, 0);
+        assert_eq!(storage.next_page_id(), 0);
     }
 
     #[test]
@@ -79,9 +501,9 @@ mod test {
             .open(temp_file_path).unwrap();
         let bytes = [0; PAGE_SIZE];
         let _ = f.write_all(&bytes);
-        let storage = Storage::<u16, &str>::from_path(temp_file_path);
+        let storage = Storage::new(Box::new(FileStore::open(temp_file_path).unwrap()), Durability::default()).unwrap();
         let _ = remove_file(temp_file_path);
-        assert_eq!(storage.next_page_id, 1);
+        assert_eq!(storage.next_page_id(), 1);
     }
 
     #[test]
@@ -93,10 +515,10 @@ mod test {
             .write(true).truncate(true).create(true)
             .open(temp_file_path).unwrap();
         let mut bytes = Vec::with_capacity(bytes_count);
-        bytes.extend(std::iter::repeat(0).take(bytes_count));
+        bytes.extend(std::iter::repeat_n(0, bytes_count));
         let _ = f.write_all(&bytes);
-        let storage = Storage::<u16, &str>::from_path(temp_file_path);
-        assert_eq!(storage.next_page_id, page_count);
+        let storage = Storage::new(Box::new(FileStore::open(temp_file_path).unwrap()), Durability::default()).unwrap();
+        assert_eq!(storage.next_page_id(), page_count);
         let _ = remove_file(temp_file_path);
     }
 }
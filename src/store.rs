@@ -0,0 +1,222 @@
+#[cfg(feature = "compression")]
+mod compressed;
+mod encrypted;
+#[cfg(all(any(target_os = "linux", target_os = "macos"), target_pointer_width = "64"))]
+mod mmap;
+
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::fs::TryLockError;
+use std::io;
+use std::path::Path;
+use std::sync::RwLock;
+use std::sync::atomic::AtomicU16;
+use std::sync::atomic::Ordering;
+
+use crate::error::Error;
+use crate::page::PAGE_SIZE;
+
+#[cfg(feature = "compression")]
+pub use compressed::CompressedStore;
+pub use encrypted::EncryptedStore;
+#[cfg(all(any(target_os = "linux", target_os = "macos"), target_pointer_width = "64"))]
+pub use mmap::MmapStore;
+#[cfg(all(any(target_os = "linux", target_os = "macos"), target_pointer_width = "64"))]
+pub use mmap::PageView;
+
+
+// raw pages by id; caching, latches and the free list are kept above it by Storage
+pub trait PageStore: Send + Sync {
+    // a page allocated but never written is an UnexpectedEof error, as past the end of a file
+    fn read_page(&self, page_id: u16, bytes: &mut [u8; PAGE_SIZE]) -> io::Result<()>;
+    fn write_page(&self, page_id: u16, bytes: &[u8; PAGE_SIZE]) -> io::Result<()>;
+    // the id of a new page past the end, it is only stored once written
+    fn allocate(&self) -> u16;
+    fn sync(&self) -> io::Result<()>;
+    // drops the pages from len on, their ids are allocated again
+    fn truncate(&self, len: u16) -> io::Result<()>;
+    // pages allocated so far
+    fn len(&self) -> u16;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+pub struct FileStore {
+    file: File,
+    next_page_id: AtomicU16,
+}
+
+impl FileStore {
+    // locked exclusively until dropped
+    pub fn open(file_path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(file_path)
+            .map_err(io_error)?;
+        lock(&file, true)?;
+        Self::new(file)
+    }
+
+    // shares the lock with other readers, writes fail
+    pub fn open_read_only(file_path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = File::open(file_path).map_err(io_error)?;
+        lock(&file, false)?;
+        Self::new(file)
+    }
+
+    fn new(file: File) -> Result<Self, Error> {
+        let next_page_id = file.metadata().map_err(io_error)?.len() / PAGE_SIZE as u64;
+        Ok(FileStore { file, next_page_id: AtomicU16::new(next_page_id as u16) })
+    }
+}
+
+// positioned io, so pages can be read from several threads through a shared file
+impl PageStore for FileStore {
+    fn read_page(&self, page_id: u16, bytes: &mut [u8; PAGE_SIZE]) -> io::Result<()> {
+        self.file.read_exact_at(bytes, PAGE_SIZE as u64 * page_id as u64)
+    }
+
+    fn write_page(&self, page_id: u16, bytes: &[u8; PAGE_SIZE]) -> io::Result<()> {
+        self.file.write_all_at(bytes, PAGE_SIZE as u64 * page_id as u64)
+    }
+
+    fn allocate(&self) -> u16 {
+        self.next_page_id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.file.sync_all()
+    }
+
+    fn truncate(&self, len: u16) -> io::Result<()> {
+        self.file.set_len(PAGE_SIZE as u64 * len as u64)?;
+        self.next_page_id.store(len, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn len(&self) -> u16 {
+        self.next_page_id.load(Ordering::SeqCst)
+    }
+}
+
+// gone with the process, for tests and caches
+#[derive(Default)]
+pub struct MemoryStore {
+    // None for pages allocated but never written
+    pages: RwLock<Vec<Option<[u8; PAGE_SIZE]>>>,
+    next_page_id: AtomicU16,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl PageStore for MemoryStore {
+    fn read_page(&self, page_id: u16, bytes: &mut [u8; PAGE_SIZE]) -> io::Result<()> {
+        match self.pages.read().unwrap().get(page_id as usize) {
+            Some(Some(page)) => {
+                *bytes = *page;
+                Ok(())
+            },
+            _ => Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+        }
+    }
+
+    fn write_page(&self, page_id: u16, bytes: &[u8; PAGE_SIZE]) -> io::Result<()> {
+        let mut pages = self.pages.write().unwrap();
+        if pages.len() <= page_id as usize {
+            pages.resize(page_id as usize + 1, None);
+        }
+        pages[page_id as usize] = Some(*bytes);
+        Ok(())
+    }
+
+    fn allocate(&self) -> u16 {
+        self.next_page_id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn truncate(&self, len: u16) -> io::Result<()> {
+        self.pages.write().unwrap().truncate(len as usize);
+        self.next_page_id.store(len, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn len(&self) -> u16 {
+        self.next_page_id.load(Ordering::SeqCst)
+    }
+}
+
+// positioned io, FileExt of unix; windows moves the file cursor as well, which nothing here relies on
+pub(crate) trait FileAt {
+    fn read_exact_at(&self, bytes: &mut [u8], offset: u64) -> io::Result<()>;
+    fn write_all_at(&self, bytes: &[u8], offset: u64) -> io::Result<()>;
+}
+
+#[cfg(unix)]
+impl FileAt for File {
+    fn read_exact_at(&self, bytes: &mut [u8], offset: u64) -> io::Result<()> {
+        std::os::unix::fs::FileExt::read_exact_at(self, bytes, offset)
+    }
+
+    fn write_all_at(&self, bytes: &[u8], offset: u64) -> io::Result<()> {
+        std::os::unix::fs::FileExt::write_all_at(self, bytes, offset)
+    }
+}
+
+#[cfg(windows)]
+impl FileAt for File {
+    fn read_exact_at(&self, mut bytes: &mut [u8], mut offset: u64) -> io::Result<()> {
+        while !bytes.is_empty() {
+            match std::os::windows::fs::FileExt::seek_read(self, bytes, offset) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                Ok(read) => {
+                    bytes = &mut bytes[read..];
+                    offset += read as u64;
+                },
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {},
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+
+    fn write_all_at(&self, mut bytes: &[u8], mut offset: u64) -> io::Result<()> {
+        while !bytes.is_empty() {
+            match std::os::windows::fs::FileExt::seek_write(self, bytes, offset) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+                Ok(written) => {
+                    bytes = &bytes[written..];
+                    offset += written as u64;
+                },
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {},
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+}
+
+// advisory, held by the open file and released when it is closed
+pub(crate) fn lock(file: &File, exclusive: bool) -> Result<(), Error> {
+    let result = if exclusive { file.try_lock() } else { file.try_lock_shared() };
+    match result {
+        Ok(()) => Ok(()),
+        Err(TryLockError::WouldBlock) => Err(Error::Locked),
+        Err(TryLockError::Error(error)) => Err(io_error(error)),
+    }
+}
+
+pub(crate) fn io_error(error: io::Error) -> Error {
+    Error::Io(error.to_string())
+}
@@ -0,0 +1,259 @@
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::fs::rename;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::sync::atomic::AtomicU16;
+use std::sync::atomic::Ordering;
+
+use crate::codec::Codec;
+use crate::error::Error;
+use crate::page::PAGE_SIZE;
+use crate::store::FileAt;
+use crate::store::PageStore;
+use crate::store::io_error;
+use crate::store::lock;
+
+const MAGIC: &[u8; 4] = b"ddbz";
+// tag, page id, stored length and the crc of the record with the crc taken as zero
+const RECORD_HEADER_LEN: usize = 9;
+const CRC_OFFSET: usize = 5;
+// a page that does not get smaller is stored as it is
+const RAW: u16 = 0x8000;
+// the latest bytes of a page
+const PAGE: u8 = 0;
+// a record without bytes that drops the pages from its length on
+const TRUNCATE: u8 = 1;
+// the log is only rewritten once this much of it is dead
+const MIN_DEAD_BYTES: u64 = 1 << 12;
+
+
+// where the latest record of a page is, the bytes after its header
+#[derive(Clone, Copy)]
+struct Extent {
+    offset: u64,
+    len: u16,
+}
+
+struct Log {
+    file: File,
+    end: u64,
+    // by page id, None for pages allocated but never written
+    extents: Vec<Option<Extent>>,
+    // bytes of records a later one replaced
+    dead: u64,
+}
+
+// pages are compressed into variable sized records appended to a log; the latest record
+// of each page wins, and the log is rewritten once most of it is dead
+pub struct CompressedStore<C> {
+    path: PathBuf,
+    codec: C,
+    log: RwLock<Log>,
+    next_page_id: AtomicU16,
+}
+
+impl<C: Codec> CompressedStore<C> {
+    // locked exclusively until dropped; a record torn by a crash is cut off
+    pub fn open(file_path: impl AsRef<Path>, codec: C) -> Result<Self, Error> {
+        let path = file_path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(io_error)?;
+        lock(&file, true)?;
+        let header = header(codec.name());
+        let mut log = Log { file, end: header.len() as u64, extents: vec![], dead: 0 };
+        if log.file.metadata().map_err(io_error)?.len() == 0 {
+            log.file.write_all_at(&header, 0).map_err(io_error)?;
+        } else {
+            check_header(&log.file, codec.name())?;
+            log.replay().map_err(io_error)?;
+        }
+        let next_page_id = log.extents.len() as u16;
+        Ok(CompressedStore { path, codec, log: RwLock::new(log), next_page_id: AtomicU16::new(next_page_id) })
+    }
+
+    fn rewrite_when_dead(&self, log: &mut Log) -> io::Result<()> {
+        if log.dead >= MIN_DEAD_BYTES && log.dead * 2 > log.end {
+            self.rewrite(log)?;
+        }
+        Ok(())
+    }
+
+    // live records are copied to a new file that then takes the place of the log
+    fn rewrite(&self, log: &mut Log) -> io::Result<()> {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".rewrite");
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path)?;
+        file.try_lock().map_err(|_| io::Error::from(io::ErrorKind::WouldBlock))?;
+        let header = header(self.codec.name());
+        file.write_all_at(&header, 0)?;
+        let mut rewritten = Log { file, end: header.len() as u64, extents: vec![], dead: 0 };
+        for (page_id, extent) in log.extents.iter().enumerate() {
+            if let Some(extent) = extent {
+                let mut bytes = vec![0; (extent.len & !RAW) as usize];
+                log.file.read_exact_at(&mut bytes, extent.offset)?;
+                rewritten.append(PAGE, page_id as u16, extent.len, &bytes)?;
+            }
+        }
+        rewritten.extents.resize(log.extents.len(), None);
+        rewritten.file.sync_all()?;
+        rename(&path, &self.path)?;
+        *log = rewritten;
+        Ok(())
+    }
+}
+
+impl<C: Codec> PageStore for CompressedStore<C> {
+    fn read_page(&self, page_id: u16, bytes: &mut [u8; PAGE_SIZE]) -> io::Result<()> {
+        let log = self.log.read().unwrap();
+        let extent = log.extents.get(page_id as usize).cloned().flatten()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        let mut stored = vec![0; (extent.len & !RAW) as usize];
+        log.file.read_exact_at(&mut stored, extent.offset)?;
+        let page = if extent.len & RAW != 0 { stored } else { self.codec.decompress(&stored)? };
+        if page.len() != PAGE_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "corrupt compressed page"));
+        }
+        bytes.copy_from_slice(&page);
+        Ok(())
+    }
+
+    fn write_page(&self, page_id: u16, bytes: &[u8; PAGE_SIZE]) -> io::Result<()> {
+        let compressed = self.codec.compress(bytes);
+        let (len, stored) = if compressed.len() < PAGE_SIZE {
+            (compressed.len() as u16, &compressed[..])
+        } else {
+            (PAGE_SIZE as u16 | RAW, &bytes[..])
+        };
+        let mut log = self.log.write().unwrap();
+        log.append(PAGE, page_id, len, stored)?;
+        self.rewrite_when_dead(&mut log)
+    }
+
+    fn allocate(&self) -> u16 {
+        self.next_page_id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.log.read().unwrap().file.sync_all()
+    }
+
+    fn truncate(&self, len: u16) -> io::Result<()> {
+        let mut log = self.log.write().unwrap();
+        log.append(TRUNCATE, 0, len, &[])?;
+        self.next_page_id.store(len, Ordering::SeqCst);
+        self.rewrite_when_dead(&mut log)
+    }
+
+    fn len(&self) -> u16 {
+        self.next_page_id.load(Ordering::SeqCst)
+    }
+}
+
+impl Log {
+    fn append(&mut self, tag: u8, page_id: u16, len: u16, bytes: &[u8]) -> io::Result<()> {
+        let mut record = Vec::with_capacity(RECORD_HEADER_LEN + bytes.len());
+        record.push(tag);
+        record.extend_from_slice(&page_id.to_le_bytes());
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(&[0; 4]);
+        record.extend_from_slice(bytes);
+        let crc = crc32(&record);
+        record[CRC_OFFSET..RECORD_HEADER_LEN].copy_from_slice(&crc.to_le_bytes());
+        self.file.write_all_at(&record, self.end)?;
+        self.apply(tag, page_id, len, self.end + RECORD_HEADER_LEN as u64);
+        self.end += record.len() as u64;
+        Ok(())
+    }
+
+    fn apply(&mut self, tag: u8, page_id: u16, len: u16, offset: u64) {
+        if tag == TRUNCATE {
+            let dropped = self.extents.drain((len as usize).min(self.extents.len())..);
+            self.dead += dropped.flatten().map(|extent| Self::record_len(extent.len)).sum::<u64>() + RECORD_HEADER_LEN as u64;
+            return;
+        }
+        if self.extents.len() <= page_id as usize {
+            self.extents.resize(page_id as usize + 1, None);
+        }
+        if let Some(replaced) = self.extents[page_id as usize].replace(Extent { offset, len }) {
+            self.dead += Self::record_len(replaced.len);
+        }
+    }
+
+    // records up to the first torn or damaged one, which is cut off with everything after it
+    fn replay(&mut self) -> io::Result<()> {
+        let file_len = self.file.metadata()?.len();
+        while self.end + RECORD_HEADER_LEN as u64 <= file_len {
+            let mut record = vec![0; RECORD_HEADER_LEN];
+            self.file.read_exact_at(&mut record, self.end)?;
+            let tag = record[0];
+            let page_id = u16::from_le_bytes([record[1], record[2]]);
+            let len = u16::from_le_bytes([record[3], record[4]]);
+            let crc = u32::from_le_bytes([record[5], record[6], record[7], record[8]]);
+            let stored_len = match tag {
+                PAGE => (len & !RAW) as usize,
+                TRUNCATE => 0,
+                _ => break,
+            };
+            if self.end + (RECORD_HEADER_LEN + stored_len) as u64 > file_len {
+                break;
+            }
+            record.resize(RECORD_HEADER_LEN + stored_len, 0);
+            self.file.read_exact_at(&mut record[RECORD_HEADER_LEN..], self.end + RECORD_HEADER_LEN as u64)?;
+            record[CRC_OFFSET..RECORD_HEADER_LEN].copy_from_slice(&[0; 4]);
+            if crc32(&record) != crc {
+                break;
+            }
+            self.apply(tag, page_id, len, self.end + RECORD_HEADER_LEN as u64);
+            self.end += record.len() as u64;
+        }
+        if self.end < file_len {
+            self.file.set_len(self.end)?;
+        }
+        Ok(())
+    }
+
+    fn record_len(len: u16) -> u64 {
+        (RECORD_HEADER_LEN + (len & !RAW) as usize) as u64
+    }
+}
+
+// the ieee polynomial, bit by bit as records are small
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn header(codec_name: &str) -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.push(codec_name.len() as u8);
+    header.extend_from_slice(codec_name.as_bytes());
+    header
+}
+
+fn check_header(file: &File, codec_name: &str) -> Result<(), Error> {
+    let mut magic = [0; 5];
+    file.read_exact_at(&mut magic, 0).map_err(io_error)?;
+    if &magic[..4] != MAGIC {
+        return Err(Error::Io("not a compressed file".to_string()));
+    }
+    let mut name = vec![0; magic[4] as usize];
+    file.read_exact_at(&mut name, 5).map_err(io_error)?;
+    if name != codec_name.as_bytes() {
+        return Err(Error::Io(format!("compressed with {}", String::from_utf8_lossy(&name))));
+    }
+    Ok(())
+}
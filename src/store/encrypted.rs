@@ -0,0 +1,185 @@
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicU16;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use crate::cipher;
+use crate::cipher::Key;
+use crate::cipher::Nonce;
+use crate::cipher::TAG_LEN;
+use crate::error::Error;
+use crate::page::PAGE_SIZE;
+use crate::store::FileAt;
+use crate::store::PageStore;
+use crate::store::io_error;
+use crate::store::lock;
+
+const MAGIC: &[u8; 4] = b"ddbe";
+const SCHEME: &str = "chacha20-poly1305";
+const HEADER_LEN: usize = 64;
+// every counter a page used is below it, it is raised RESERVED at a time before pages use the counters
+const MARK_OFFSET: usize = 24;
+const RESERVED: u64 = 1024;
+// the tag of the header under a nonce no page uses, it tells a wrong key on open
+const KEY_CHECK_OFFSET: usize = HEADER_LEN - TAG_LEN;
+// write counter, encrypted page, tag
+const COUNTER_LEN: usize = 8;
+const RECORD_LEN: usize = COUNTER_LEN + PAGE_SIZE + TAG_LEN;
+
+
+// every page is sealed on its own; the nonce is a counter bumped by every write and the page id,
+// which is also authenticated, so a page copied over another one fails to open
+pub struct EncryptedStore {
+    file: File,
+    key: Key,
+    next_page_id: AtomicU16,
+    // never repeats under a key, the nonce of a page changes with every write
+    counter: AtomicU64,
+    // as written in the header
+    mark: Mutex<u64>,
+}
+
+impl EncryptedStore {
+    // locked exclusively until dropped; a new file records the scheme and the key check
+    pub fn open(file_path: impl AsRef<Path>, key: [u8; 32]) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(file_path)
+            .map_err(io_error)?;
+        lock(&file, true)?;
+        let file_len = file.metadata().map_err(io_error)?.len() as usize;
+        // pages cut off by a truncate may have used counters past the ones left, so they start at the mark
+        let mark = if file_len == 0 {
+            file.write_all_at(&header(&key, 0), 0).map_err(io_error)?;
+            0
+        } else {
+            check_header(&file, &key)?
+        };
+        let pages = file_len.saturating_sub(HEADER_LEN) / RECORD_LEN;
+        Ok(EncryptedStore {
+            file,
+            key,
+            next_page_id: AtomicU16::new(pages as u16),
+            counter: AtomicU64::new(mark),
+            mark: Mutex::new(mark),
+        })
+    }
+
+    // the header is synced before a page sealed with the counter can reach the file
+    fn reserve(&self, counter: u64) -> io::Result<()> {
+        let mut mark = self.mark.lock().unwrap();
+        if counter < *mark {
+            return Ok(());
+        }
+        let raised = counter + RESERVED;
+        self.file.write_all_at(&header(&self.key, raised), 0)?;
+        self.file.sync_data()?;
+        *mark = raised;
+        Ok(())
+    }
+}
+
+impl PageStore for EncryptedStore {
+    fn read_page(&self, page_id: u16, bytes: &mut [u8; PAGE_SIZE]) -> io::Result<()> {
+        let mut record = [0; RECORD_LEN];
+        self.file.read_exact_at(&mut record, offset(page_id))?;
+        let mut counter = [0; COUNTER_LEN];
+        counter.copy_from_slice(&record[..COUNTER_LEN]);
+        let mut tag = [0; TAG_LEN];
+        tag.copy_from_slice(&record[COUNTER_LEN + PAGE_SIZE..]);
+        // opened aside, a page that fails authentication leaves bytes as they were
+        let mut page = [0; PAGE_SIZE];
+        page.copy_from_slice(&record[COUNTER_LEN..COUNTER_LEN + PAGE_SIZE]);
+        let nonce = nonce(u64::from_le_bytes(counter), page_id);
+        if !cipher::open(&self.key, &nonce, &page_id.to_le_bytes(), &mut page, &tag) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "page failed authentication"));
+        }
+        *bytes = page;
+        Ok(())
+    }
+
+    fn write_page(&self, page_id: u16, bytes: &[u8; PAGE_SIZE]) -> io::Result<()> {
+        let counter = self.counter.fetch_add(1, Ordering::SeqCst);
+        self.reserve(counter)?;
+        let mut page = *bytes;
+        let tag = cipher::seal(&self.key, &nonce(counter, page_id), &page_id.to_le_bytes(), &mut page);
+        let mut record = Vec::with_capacity(RECORD_LEN);
+        record.extend_from_slice(&counter.to_le_bytes());
+        record.extend_from_slice(&page);
+        record.extend_from_slice(&tag);
+        self.file.write_all_at(&record, offset(page_id))
+    }
+
+    fn allocate(&self) -> u16 {
+        self.next_page_id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.file.sync_all()
+    }
+
+    fn truncate(&self, len: u16) -> io::Result<()> {
+        self.file.set_len(offset(len))?;
+        self.next_page_id.store(len, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn len(&self) -> u16 {
+        self.next_page_id.load(Ordering::SeqCst)
+    }
+}
+
+fn offset(page_id: u16) -> u64 {
+    (HEADER_LEN + RECORD_LEN * page_id as usize) as u64
+}
+
+fn nonce(counter: u64, page_id: u16) -> Nonce {
+    let mut nonce = [0; 12];
+    nonce[..8].copy_from_slice(&counter.to_le_bytes());
+    nonce[8..10].copy_from_slice(&page_id.to_le_bytes());
+    nonce
+}
+
+// page nonces end in two zero bytes, the nonce of the header in two 0xFF ones and the mark changes it
+fn key_check_nonce(mark: u64) -> Nonce {
+    let mut nonce = [0xFF; 12];
+    nonce[..8].copy_from_slice(&mark.to_le_bytes());
+    nonce
+}
+
+fn header(key: &Key, mark: u64) -> [u8; HEADER_LEN] {
+    let mut header = [0; HEADER_LEN];
+    header[..4].copy_from_slice(MAGIC);
+    header[4] = SCHEME.len() as u8;
+    header[5..5 + SCHEME.len()].copy_from_slice(SCHEME.as_bytes());
+    header[MARK_OFFSET..MARK_OFFSET + 8].copy_from_slice(&mark.to_le_bytes());
+    let tag = cipher::seal(key, &key_check_nonce(mark), &header[..KEY_CHECK_OFFSET], &mut []);
+    header[KEY_CHECK_OFFSET..].copy_from_slice(&tag);
+    header
+}
+
+// returns the mark, which the tag authenticates
+fn check_header(file: &File, key: &Key) -> Result<u64, Error> {
+    let mut stored = [0; HEADER_LEN];
+    file.read_exact_at(&mut stored, 0).map_err(io_error)?;
+    let expected = header(key, 0);
+    if stored[..MARK_OFFSET] != expected[..MARK_OFFSET] {
+        return Err(Error::Io("not an encrypted file".to_string()));
+    }
+    let mut mark = [0; 8];
+    mark.copy_from_slice(&stored[MARK_OFFSET..MARK_OFFSET + 8]);
+    let mark = u64::from_le_bytes(mark);
+    let mut tag = [0; TAG_LEN];
+    tag.copy_from_slice(&stored[KEY_CHECK_OFFSET..]);
+    if !cipher::open(key, &key_check_nonce(mark), &stored[..KEY_CHECK_OFFSET], &mut [], &tag) {
+        return Err(Error::WrongKey);
+    }
+    Ok(mark)
+}
@@ -0,0 +1,205 @@
+use std::ffi::c_int;
+use std::ffi::c_long;
+use std::ffi::c_void;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::ops::Deref;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr;
+use std::sync::Mutex;
+use std::sync::RwLock;
+use std::sync::RwLockReadGuard;
+use std::sync::atomic::AtomicU16;
+use std::sync::atomic::Ordering;
+
+use crate::error::Error;
+use crate::page::PAGE_SIZE;
+use crate::store::PageStore;
+use crate::store::io_error;
+use crate::store::lock;
+
+
+// the values of 64 bit linux and macos, the only targets the module is built for; off_t is 64 bits there
+const PROT_READ: c_int = 1;
+const PROT_WRITE: c_int = 2;
+const MAP_SHARED: c_int = 1;
+#[cfg(target_os = "linux")]
+const MS_SYNC: c_int = 4;
+#[cfg(target_os = "macos")]
+const MS_SYNC: c_int = 0x10;
+#[cfg(target_os = "linux")]
+const SC_PAGESIZE: c_int = 30;
+#[cfg(target_os = "macos")]
+const SC_PAGESIZE: c_int = 29;
+// address space reserved up front, doubled whenever the file outgrows it
+const MIN_MAPPED_LEN: usize = 1 << 16;
+
+extern "C" {
+    fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: i64) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    fn msync(addr: *mut c_void, len: usize, flags: c_int) -> c_int;
+    fn sysconf(name: c_int) -> c_long;
+}
+
+struct Mapping {
+    ptr: *mut u8,
+    // may run past the end of the file, only pages inside the file are ever touched
+    len: usize,
+    file_len: usize,
+}
+
+// pages are read straight from the mapping, no syscall per page; the file is exactly as long as the pages written
+pub struct MmapStore {
+    file: File,
+    mapping: RwLock<Mapping>,
+    next_page_id: AtomicU16,
+    // bytes written since the last sync
+    dirty: Mutex<Option<(usize, usize)>>,
+}
+
+// the mapping is only touched under its lock and unmapped on drop
+unsafe impl Send for MmapStore {}
+unsafe impl Sync for MmapStore {}
+
+impl MmapStore {
+    // locked exclusively until dropped
+    pub fn open(file_path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(file_path)
+            .map_err(io_error)?;
+        lock(&file, true)?;
+        let file_len = file.metadata().map_err(io_error)?.len() as usize;
+        let ptr = map(&file, mapped_len(file_len, MIN_MAPPED_LEN)).map_err(io_error)?;
+        Ok(MmapStore {
+            file,
+            mapping: RwLock::new(Mapping { ptr, len: mapped_len(file_len, MIN_MAPPED_LEN), file_len }),
+            next_page_id: AtomicU16::new((file_len / PAGE_SIZE) as u16),
+            dirty: Mutex::new(None),
+        })
+    }
+
+    // a view into the mapping, writes to the page wait until it is dropped
+    pub fn page(&self, page_id: u16) -> io::Result<PageView<'_>> {
+        let mapping = self.mapping.read().unwrap();
+        let offset = PAGE_SIZE * page_id as usize;
+        if offset + PAGE_SIZE > mapping.file_len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        Ok(PageView { mapping, offset })
+    }
+
+    fn grow(&self, file_len: usize) -> io::Result<()> {
+        let mut mapping = self.mapping.write().unwrap();
+        if file_len <= mapping.file_len {
+            return Ok(());
+        }
+        self.file.set_len(file_len as u64)?;
+        mapping.file_len = file_len;
+        if file_len > mapping.len {
+            let len = mapped_len(file_len, mapping.len * 2);
+            let ptr = map(&self.file, len)?;
+            unsafe { munmap(mapping.ptr as *mut c_void, mapping.len) };
+            mapping.ptr = ptr;
+            mapping.len = len;
+        }
+        Ok(())
+    }
+}
+
+impl PageStore for MmapStore {
+    fn read_page(&self, page_id: u16, bytes: &mut [u8; PAGE_SIZE]) -> io::Result<()> {
+        *bytes = *self.page(page_id)?;
+        Ok(())
+    }
+
+    fn write_page(&self, page_id: u16, bytes: &[u8; PAGE_SIZE]) -> io::Result<()> {
+        let offset = PAGE_SIZE * page_id as usize;
+        self.grow(offset + PAGE_SIZE)?;
+        // exclusive so no PageView sees the page half written
+        #[allow(clippy::readonly_write_lock)]
+        let mapping = self.mapping.write().unwrap();
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), mapping.ptr.add(offset), PAGE_SIZE) };
+        let mut dirty = self.dirty.lock().unwrap();
+        *dirty = Some(match *dirty {
+            Some((start, end)) => (start.min(offset), end.max(offset + PAGE_SIZE)),
+            None => (offset, offset + PAGE_SIZE),
+        });
+        Ok(())
+    }
+
+    fn allocate(&self) -> u16 {
+        self.next_page_id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    // msync wants the start aligned to the os page
+    fn sync(&self) -> io::Result<()> {
+        let mapping = self.mapping.read().unwrap();
+        if let Some((start, end)) = self.dirty.lock().unwrap().take() {
+            let start = start - start % os_page_size();
+            let result = unsafe { msync(mapping.ptr.add(start) as *mut c_void, end - start, MS_SYNC) };
+            if result != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        self.file.sync_all()
+    }
+
+    // the mapping keeps its length, pages past the end of the file are never touched
+    fn truncate(&self, len: u16) -> io::Result<()> {
+        let mut mapping = self.mapping.write().unwrap();
+        let file_len = PAGE_SIZE * len as usize;
+        self.file.set_len(file_len as u64)?;
+        mapping.file_len = mapping.file_len.min(file_len);
+        let mut dirty = self.dirty.lock().unwrap();
+        *dirty = dirty.map(|(start, end)| (start, end.min(file_len))).filter(|(start, end)| start < end);
+        self.next_page_id.store(len, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn len(&self) -> u16 {
+        self.next_page_id.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for MmapStore {
+    fn drop(&mut self) {
+        let mapping = self.mapping.get_mut().unwrap();
+        unsafe { munmap(mapping.ptr as *mut c_void, mapping.len) };
+    }
+}
+
+pub struct PageView<'a> {
+    mapping: RwLockReadGuard<'a, Mapping>,
+    offset: usize,
+}
+
+impl Deref for PageView<'_> {
+    type Target = [u8; PAGE_SIZE];
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*(self.mapping.ptr.add(self.offset) as *const [u8; PAGE_SIZE]) }
+    }
+}
+
+fn map(file: &File, len: usize) -> io::Result<*mut u8> {
+    let ptr = unsafe { mmap(ptr::null_mut(), len, PROT_READ | PROT_WRITE, MAP_SHARED, file.as_raw_fd(), 0) };
+    if ptr as isize == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ptr as *mut u8)
+    }
+}
+
+fn mapped_len(file_len: usize, at_least: usize) -> usize {
+    file_len.max(at_least).next_power_of_two()
+}
+
+fn os_page_size() -> usize {
+    unsafe { sysconf(SC_PAGESIZE) as usize }
+}
@@ -0,0 +1,253 @@
+use std::fmt::Write;
+
+
+// how keys and values are written in exports and read back by imports
+pub trait SlotText: Sized {
+    fn to_text(&self) -> String;
+    fn from_text(text: &str) -> Result<Self, String>;
+}
+
+macro_rules! number_text {
+    ($($t:ty),*) => {$(
+        impl SlotText for $t {
+            fn to_text(&self) -> String {
+                self.to_string()
+            }
+
+            fn from_text(text: &str) -> Result<Self, String> {
+                text.parse().map_err(|e| format!("{}: {:?}", e, text))
+            }
+        }
+    )*};
+}
+
+number_text!(u8, u16, u32);
+
+impl SlotText for String {
+    fn to_text(&self) -> String {
+        self.clone()
+    }
+
+    fn from_text(text: &str) -> Result<Self, String> {
+        Ok(text.to_string())
+    }
+}
+
+// lowercase hex, two digits a byte
+impl SlotText for Vec<u8> {
+    fn to_text(&self) -> String {
+        self.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn from_text(text: &str) -> Result<Self, String> {
+        if !text.len().is_multiple_of(2) || !text.is_ascii() {
+            return Err(format!("not hex bytes: {:?}", text));
+        }
+        (0..text.len()).step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).map_err(|e| format!("{}: {:?}", e, text)))
+            .collect()
+    }
+}
+
+// line the record starts on, key and value
+type Record = (usize, String, String);
+// line and what is wrong with it
+type ParseError = (usize, String);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    // {"key":"...","value":"..."} a line, both as json strings
+    #[default]
+    JsonLines,
+    // key,value a record without a header, quoted when needed
+    Csv,
+}
+
+impl ExportFormat {
+    pub(crate) fn record(&self, key: &str, value: &str) -> String {
+        match self {
+            ExportFormat::JsonLines => format!("{{\"key\":{},\"value\":{}}}\n", json_string(key), json_string(value)),
+            ExportFormat::Csv => format!("{},{}\r\n", csv_field(key), csv_field(value)),
+        }
+    }
+
+    pub(crate) fn parse(&self, input: &str) -> Result<Vec<Record>, ParseError> {
+        match self {
+            ExportFormat::JsonLines => input.lines().enumerate()
+                .filter(|(_, line)| !line.trim().is_empty())
+                .map(|(index, line)| match parse_json_line(line) {
+                    Ok((key, value)) => Ok((index + 1, key, value)),
+                    Err(message) => Err((index + 1, message)),
+                })
+                .collect(),
+            ExportFormat::Csv => parse_csv(input),
+        }
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut json = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(json, "\\u{:04x}", c as u32); },
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+// only objects of string members, which is all an export writes
+fn parse_json_line(line: &str) -> Result<(String, String), String> {
+    let mut chars = line.chars().peekable();
+    let (mut key, mut value) = (None, None);
+    expect(&mut chars, '{')?;
+    loop {
+        let name = parse_json_string(&mut chars)?;
+        expect(&mut chars, ':')?;
+        let text = parse_json_string(&mut chars)?;
+        match name.as_str() {
+            "key" => key = Some(text),
+            "value" => value = Some(text),
+            other => return Err(format!("unknown member {:?}", other)),
+        }
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            Some(',') => continue,
+            Some('}') => break,
+            other => return Err(format!("expected , or }} but found {:?}", other)),
+        }
+    }
+    skip_whitespace(&mut chars);
+    if let Some(c) = chars.next() {
+        return Err(format!("trailing {:?}", c));
+    }
+    match (key, value) {
+        (Some(key), Some(value)) => Ok((key, value)),
+        _ => Err("key or value missing".to_string()),
+    }
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+fn skip_whitespace(chars: &mut Chars) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn expect(chars: &mut Chars, expected: char) -> Result<(), String> {
+    skip_whitespace(chars);
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        other => Err(format!("expected {:?} but found {:?}", expected, other)),
+    }
+}
+
+fn parse_json_string(chars: &mut Chars) -> Result<String, String> {
+    expect(chars, '"')?;
+    let mut text = String::new();
+    loop {
+        match chars.next().ok_or("unterminated string")? {
+            '"' => return Ok(text),
+            '\\' => match chars.next().ok_or("unterminated string")? {
+                '"' => text.push('"'),
+                '\\' => text.push('\\'),
+                '/' => text.push('/'),
+                'b' => text.push('\u{8}'),
+                'f' => text.push('\u{c}'),
+                'n' => text.push('\n'),
+                'r' => text.push('\r'),
+                't' => text.push('\t'),
+                'u' => {
+                    let high = parse_hex4(chars)?;
+                    let code = if (0xD800..0xDC00).contains(&high) {
+                        if chars.next() != Some('\\') || chars.next() != Some('u') {
+                            return Err("unpaired surrogate".to_string());
+                        }
+                        let low = parse_hex4(chars)?;
+                        if !(0xDC00..0xE000).contains(&low) {
+                            return Err("unpaired surrogate".to_string());
+                        }
+                        0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                    } else {
+                        high
+                    };
+                    text.push(char::from_u32(code).ok_or(format!("invalid code point {:x}", code))?);
+                },
+                other => return Err(format!("invalid escape \\{}", other)),
+            },
+            c => text.push(c),
+        }
+    }
+}
+
+fn parse_hex4(chars: &mut Chars) -> Result<u32, String> {
+    let hex = chars.take(4).collect::<String>();
+    u32::from_str_radix(&hex, 16).map_err(|_| format!("invalid escape \\u{}", hex))
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+// quoted fields may hold commas, doubled quotes and line breaks
+fn parse_csv(input: &str) -> Result<Vec<Record>, ParseError> {
+    let mut records = vec![];
+    let mut fields: Vec<String> = vec![];
+    let mut field = String::new();
+    let (mut line, mut record_line) = (1, 1);
+    let mut quoted = false;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            },
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {},
+            '\n' if !quoted => {
+                line += 1;
+                if !fields.is_empty() || !field.is_empty() {
+                    fields.push(std::mem::take(&mut field));
+                    records.push(csv_record(std::mem::take(&mut fields), record_line)?);
+                }
+                record_line = line;
+            },
+            c => {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            },
+        }
+    }
+    if quoted {
+        return Err((record_line, "unterminated quote".to_string()));
+    }
+    if !fields.is_empty() || !field.is_empty() {
+        fields.push(field);
+        records.push(csv_record(fields, record_line)?);
+    }
+    Ok(records)
+}
+
+fn csv_record(mut fields: Vec<String>, line: usize) -> Result<Record, ParseError> {
+    if fields.len() != 2 {
+        return Err((line, format!("expected 2 fields but found {}", fields.len())));
+    }
+    let value = fields.pop().unwrap();
+    Ok((line, fields.pop().unwrap(), value))
+}